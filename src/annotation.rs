@@ -4,10 +4,16 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+mod bloom;
 mod cardinality;
+mod digest;
 mod index;
 mod keyed;
 mod unit;
 
+pub use bloom::*;
 pub use cardinality::*;
+pub use digest::*;
 pub use keyed::*;
+
+pub(crate) use index::Index;
@@ -0,0 +1,283 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use crate::annotation::Keyed;
+use crate::block::{Block, Blocks};
+use crate::persistent::PersistentNStack;
+#[cfg(feature = "store")]
+use crate::store::StoredNStack;
+use crate::NStack;
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+
+use microkelvin::{Branch, BranchMut, Child, Compound, Step, Walk, Walker};
+use ranno::Annotation;
+
+/// Number of 64 bit words in a [`Bloom`] filter
+const WORDS: usize = 4;
+/// Number of bits in a [`Bloom`] filter
+const BITS: u64 = (WORDS * 64) as u64;
+/// Number of bits set per inserted key
+const HASHES: u64 = 3;
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    /// Returns true if the stack contains an element with the given key
+    pub fn contains_key<K>(&self, key: &K) -> bool
+    where
+        T: Keyed<K>,
+        A: Borrow<Bloom<K>>,
+        K: Hash + PartialEq,
+    {
+        self.find_key(key).is_some()
+    }
+
+    /// Construct a [`Branch`] pointing to the first element with the given
+    /// key, if any
+    pub fn find_key<K>(&self, key: &K) -> Option<Branch<'_, Self, A>>
+    where
+        T: Keyed<K>,
+        A: Borrow<Bloom<K>>,
+        K: Hash + PartialEq,
+    {
+        Branch::walk(self, FindKey(key))
+    }
+
+    /// Construct a [`BranchMut`] pointing to the first element with the given
+    /// key, if any
+    pub fn find_key_mut<K>(&mut self, key: &K) -> Option<BranchMut<'_, Self, A>>
+    where
+        T: Keyed<K>,
+        A: Borrow<Bloom<K>>,
+        K: Hash + PartialEq,
+    {
+        BranchMut::walk(self, FindKey(key))
+    }
+}
+
+/// A fixed-size bloom filter over the keys of the leaves in a subtree.
+///
+/// A negative answer from [`may_contain`] is definitive, while a positive one
+/// might be a false positive.
+///
+/// [`may_contain`]: Bloom::may_contain
+pub struct Bloom<K> {
+    bits: [u64; WORDS],
+    _marker: PhantomData<K>,
+}
+
+impl<K> Bloom<K> {
+    /// Creates a new empty filter
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; WORDS],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns true if no key was ever inserted in the filter
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|word| *word == 0)
+    }
+
+    /// Merge all the keys of `other` into this filter
+    pub fn union(&mut self, other: &Self) {
+        for (word, other) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word |= other;
+        }
+    }
+
+    /// Returns the words of the filter
+    pub(crate) fn words(&self) -> &[u64; WORDS] {
+        &self.bits
+    }
+
+    /// Returns the words of the filter, mutably
+    pub(crate) fn words_mut(&mut self) -> &mut [u64; WORDS] {
+        &mut self.bits
+    }
+}
+
+impl<K> Bloom<K>
+where
+    K: Hash,
+{
+    /// Insert a key in the filter
+    pub fn insert(&mut self, key: &K) {
+        for bit in bits(key) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns false if the key was definitely never inserted in the filter
+    pub fn may_contain(&self, key: &K) -> bool {
+        bits(key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// The positions of the bits set for a key, derived from a single hash using
+/// double hashing
+fn bits<K: Hash>(key: &K) -> impl Iterator<Item = u64> {
+    let mut hasher = Fnv::default();
+    key.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let h1 = hash & 0xffff_ffff;
+    let h2 = (hash >> 32) | 1;
+
+    (0..HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % BITS)
+}
+
+/// 64 bit FNV-1a hasher
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl<K> Default for Bloom<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Clone for Bloom<K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K> Copy for Bloom<K> {}
+
+impl<K> PartialEq for Bloom<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<K> Eq for Bloom<K> {}
+
+impl<K> fmt::Debug for Bloom<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Bloom").field(&self.bits).finish()
+    }
+}
+
+impl<K> Bloom<K>
+where
+    K: Hash,
+{
+    fn from_blocks<T, S>(stack: &S) -> Self
+    where
+        T: Keyed<K>,
+        S: Blocks<T, Self>,
+        Self: Annotation<S>,
+    {
+        let mut bloom = Self::new();
+
+        match stack.block() {
+            Block::Leaf(leaf) => {
+                for key in leaf.iter().flatten().map(Keyed::key) {
+                    bloom.insert(key);
+                }
+            }
+            Block::Node(node) => {
+                for annotated in node.iter().flatten() {
+                    bloom.union(&annotated.anno());
+                }
+            }
+            Block::Stored(anno) => return *anno,
+        }
+
+        bloom
+    }
+}
+
+impl<T, K> Annotation<NStack<T, Bloom<K>>> for Bloom<K>
+where
+    T: Keyed<K>,
+    K: Hash,
+{
+    fn from_child(stack: &NStack<T, Bloom<K>>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+impl<T, K> Annotation<PersistentNStack<T, Bloom<K>>> for Bloom<K>
+where
+    T: Keyed<K>,
+    K: Hash,
+{
+    fn from_child(stack: &PersistentNStack<T, Bloom<K>>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+#[cfg(feature = "store")]
+impl<T, K> Annotation<StoredNStack<T, Bloom<K>>> for Bloom<K>
+where
+    T: Keyed<K>,
+    K: Hash,
+{
+    fn from_child(stack: &StoredNStack<T, Bloom<K>>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+pub(crate) struct FindKey<'a, K>(pub(crate) &'a K);
+
+impl<'a, C, A, K> Walker<C, A> for FindKey<'a, K>
+where
+    C: Compound<A>,
+    C::Leaf: Keyed<K>,
+    A: Annotation<C> + Borrow<Bloom<K>>,
+    K: Hash + PartialEq,
+{
+    fn walk(&mut self, walk: Walk<C, A>) -> Step {
+        for i in 0.. {
+            match walk.child(i) {
+                Child::Leaf(l) => {
+                    if l.key() == self.0 {
+                        return Step::Found(i);
+                    }
+                }
+                Child::Node(n) => {
+                    let anno = n.anno();
+                    let bloom: &Bloom<K> = (*anno).borrow();
+                    if bloom.may_contain(self.0) {
+                        return Step::Into(i);
+                    }
+                }
+                Child::Empty => (),
+                // the key might have been a false positive of the filter, so
+                // keep searching the rest of the tree
+                Child::EndOfNode => return Step::Advance,
+            }
+        }
+        unreachable!()
+    }
+}
@@ -4,6 +4,10 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+use crate::block::{Block, Blocks};
+use crate::persistent::PersistentNStack;
+#[cfg(feature = "store")]
+use crate::store::StoredNStack;
 use crate::NStack;
 
 use core::ops::Deref;
@@ -11,7 +15,19 @@ use core::ops::Deref;
 use ranno::Annotation;
 
 /// The cardinality of the NStack.
+///
+/// Counts the elements, but not the holes left by [`Tombstoned::remove_hole`],
+/// which [`Tombstoned::positional_len`] does.
+///
+/// [`Tombstoned::remove_hole`]: crate::tombstone::Tombstoned::remove_hole
+/// [`Tombstoned::positional_len`]: crate::tombstone::Tombstoned::positional_len
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    rkyv(derive(Debug, PartialEq, PartialOrd))
+)]
 pub struct Cardinality(u64);
 
 impl From<u64> for Cardinality {
@@ -34,25 +50,56 @@ impl PartialEq<u64> for Cardinality {
     }
 }
 
-impl<T> Annotation<NStack<T, Cardinality>> for Cardinality {
-    fn from_child(stack: &NStack<T, Cardinality>) -> Self {
+#[cfg(feature = "rkyv")]
+impl PartialEq<u64> for ArchivedCardinality {
+    fn eq(&self, other: &u64) -> bool {
+        self.0.to_native().eq(other)
+    }
+}
+
+impl Cardinality {
+    fn from_blocks<T, S>(stack: &S) -> Self
+    where
+        S: Blocks<T, Self>,
+        Self: Annotation<S>,
+    {
         let mut cardinality = 0;
 
-        match stack {
-            NStack::Leaf(leaf) => {
+        match stack.block() {
+            Block::Leaf(leaf) => {
                 for _ in leaf.iter().flatten() {
                     cardinality += 1;
                 }
             }
-            NStack::Node(node) => {
+            Block::Node(node) => {
                 for a in node.iter().flatten() {
                     let anno = a.anno();
                     let c = &*anno;
                     cardinality += c.0;
                 }
             }
+            Block::Stored(anno) => return *anno,
         }
 
         cardinality.into()
     }
 }
+
+impl<T> Annotation<NStack<T, Cardinality>> for Cardinality {
+    fn from_child(stack: &NStack<T, Cardinality>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+impl<T> Annotation<PersistentNStack<T, Cardinality>> for Cardinality {
+    fn from_child(stack: &PersistentNStack<T, Cardinality>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+#[cfg(feature = "store")]
+impl<T> Annotation<StoredNStack<T, Cardinality>> for Cardinality {
+    fn from_child(stack: &StoredNStack<T, Cardinality>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
@@ -0,0 +1,28 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use crate::annotation::{Bloom, Cardinality, MaxKey};
+
+/// Trait for annotations that can identify the leaves of a subtree, such as
+/// a cryptographic hash.
+///
+/// Used to skip equal subtrees without descending into them. Annotations that
+/// carry no such information can rely on the default implementation.
+pub trait Digest {
+    /// Returns true if the subtrees annotated by `self` and `other` are known
+    /// to hold the same leaves
+    fn digest_eq(&self, _other: &Self) -> bool {
+        false
+    }
+}
+
+impl Digest for () {}
+
+impl Digest for Cardinality {}
+
+impl<K> Digest for MaxKey<K> {}
+
+impl<K> Digest for Bloom<K> {}
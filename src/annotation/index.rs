@@ -9,7 +9,7 @@ use crate::NStack;
 
 use core::borrow::Borrow;
 
-use microkelvin::{Branch, BranchMut, Child, Step, Walk, Walker};
+use microkelvin::{Branch, BranchMut, Child, Compound, Step, Walk, Walker};
 use ranno::Annotation;
 
 impl<T, A> NStack<T, A>
@@ -17,23 +17,24 @@ where
     A: Annotation<Self> + Borrow<Cardinality>,
 {
     /// Construct a [`Branch`] pointing to the `nth` element, if any
-    pub fn nth(&self, index: u64) -> Option<Branch<Self, A>> {
+    pub fn nth(&self, index: u64) -> Option<Branch<'_, Self, A>> {
         Branch::walk(self, Index(index))
     }
 
     /// Construct a [`BranchMut`] pointing to the `nth` element, if any
-    pub fn nth_mut(&mut self, index: u64) -> Option<BranchMut<Self, A>> {
+    pub fn nth_mut(&mut self, index: u64) -> Option<BranchMut<'_, Self, A>> {
         BranchMut::walk(self, Index(index))
     }
 }
 
-struct Index(u64);
+pub(crate) struct Index(pub(crate) u64);
 
-impl<T, A> Walker<NStack<T, A>, A> for Index
+impl<C, A> Walker<C, A> for Index
 where
-    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+    C: Compound<A>,
+    A: Annotation<C> + Borrow<Cardinality>,
 {
-    fn walk(&mut self, walk: Walk<NStack<T, A>, A>) -> Step {
+    fn walk(&mut self, walk: Walk<C, A>) -> Step {
         for i in 0.. {
             match walk.child(i) {
                 Child::Leaf(_) => {
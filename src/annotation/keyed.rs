@@ -4,13 +4,18 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+use crate::block::{Block, Blocks};
+use crate::find::Filter;
+use crate::persistent::PersistentNStack;
+#[cfg(feature = "store")]
+use crate::store::StoredNStack;
 use crate::NStack;
 
 use core::borrow::Borrow;
 use core::cmp::{Ordering, PartialOrd};
 use core::marker::PhantomData;
 
-use microkelvin::{Branch, BranchMut, Child, Step, Walk, Walker};
+use microkelvin::{Branch, BranchMut, Child, Compound, Step, Walk, Walker};
 use ranno::Annotation;
 
 impl<T, A> NStack<T, A>
@@ -18,7 +23,7 @@ where
     A: Annotation<NStack<T, A>>,
 {
     /// Construct a [`Branch`] pointing to the element with the largest key
-    pub fn max_key<K>(&self) -> Option<Branch<Self, A>>
+    pub fn max_key<K>(&self) -> Option<Branch<'_, Self, A>>
     where
         T: Keyed<K>,
         A: Borrow<MaxKey<K>>,
@@ -28,7 +33,7 @@ where
     }
 
     /// Construct a [`BranchMut`] pointing to the element with the largest key
-    pub fn max_key_mut<K>(&mut self) -> Option<BranchMut<Self, A>>
+    pub fn max_key_mut<K>(&mut self) -> Option<BranchMut<'_, Self, A>>
     where
         T: Keyed<K>,
         A: Borrow<MaxKey<K>>,
@@ -50,20 +55,20 @@ impl<K> Keyed<K> for K {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
 pub enum MaxKey<K> {
     /// Every other key is larger
+    #[default]
     NegativeInfinity,
     /// Actual max key
     Maximum(K),
 }
 
-impl<K> Default for MaxKey<K> {
-    fn default() -> Self {
-        Self::NegativeInfinity
-    }
-}
-
 impl<K> PartialEq<K> for MaxKey<K>
 where
     K: PartialEq,
@@ -100,23 +105,27 @@ where
     }
 }
 
-impl<T, K> Annotation<NStack<T, MaxKey<K>>> for MaxKey<K>
+impl<K> MaxKey<K>
 where
-    T: Keyed<K>,
     K: Clone + PartialOrd,
 {
-    fn from_child(stack: &NStack<T, MaxKey<K>>) -> Self {
+    fn from_blocks<T, S>(stack: &S) -> Self
+    where
+        T: Keyed<K>,
+        S: Blocks<T, Self>,
+        Self: Annotation<S>,
+    {
         let mut max_key = Self::NegativeInfinity;
 
-        match stack {
-            NStack::Leaf(leaf) => {
+        match stack.block() {
+            Block::Leaf(leaf) => {
                 for key in leaf.iter().flatten().map(Keyed::key) {
                     if &max_key < key {
                         max_key = MaxKey::Maximum(key.clone());
                     }
                 }
             }
-            NStack::Node(node) => {
+            Block::Node(node) => {
                 for annotated in node.iter().flatten() {
                     let key = &*annotated.anno();
                     if &max_key < key {
@@ -124,27 +133,60 @@ where
                     }
                 }
             }
+            Block::Stored(anno) => return anno.clone(),
         }
 
         max_key
     }
 }
 
-struct FindMaxKey<K>(PhantomData<K>);
+impl<T, K> Annotation<NStack<T, MaxKey<K>>> for MaxKey<K>
+where
+    T: Keyed<K>,
+    K: Clone + PartialOrd,
+{
+    fn from_child(stack: &NStack<T, MaxKey<K>>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+impl<T, K> Annotation<PersistentNStack<T, MaxKey<K>>> for MaxKey<K>
+where
+    T: Keyed<K>,
+    K: Clone + PartialOrd,
+{
+    fn from_child(stack: &PersistentNStack<T, MaxKey<K>>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+#[cfg(feature = "store")]
+impl<T, K> Annotation<StoredNStack<T, MaxKey<K>>> for MaxKey<K>
+where
+    T: Keyed<K>,
+    K: Clone + PartialOrd,
+{
+    fn from_child(stack: &StoredNStack<T, MaxKey<K>>) -> Self {
+        Self::from_blocks(stack)
+    }
+}
+
+pub(crate) struct FindMaxKey<K>(PhantomData<K>);
 
 impl<K> Default for FindMaxKey<K> {
     fn default() -> Self {
-        FindMaxKey(PhantomData::default())
+        FindMaxKey(PhantomData)
     }
 }
 
-impl<T, A, K> Walker<NStack<T, A>, A> for FindMaxKey<K>
+impl<C, A, K> Walker<C, A> for FindMaxKey<K>
 where
-    T: Keyed<K>,
-    A: Annotation<NStack<T, A>> + Borrow<MaxKey<K>>,
+    C: Compound<A>,
+    C::Leaf: Keyed<K>,
+    A: Annotation<C> + Borrow<MaxKey<K>>,
     K: Clone + PartialOrd,
 {
-    fn walk(&mut self, walk: Walk<NStack<T, A>, A>) -> Step {
+    fn walk(&mut self, walk: Walk<C, A>) -> Step {
         let mut current_max = MaxKey::NegativeInfinity;
         let mut current_step = Step::Abort;
 
@@ -173,3 +215,21 @@ where
         unreachable!()
     }
 }
+
+/// A [`Filter`] matching every element with a key larger than the given one
+pub struct KeyAbove<K>(pub K);
+
+impl<T, A, K> Filter<T, A> for KeyAbove<K>
+where
+    T: Keyed<K>,
+    A: Borrow<MaxKey<K>>,
+    K: PartialOrd,
+{
+    fn anno(&mut self, anno: &A) -> bool {
+        anno.borrow() > &self.0
+    }
+
+    fn leaf(&mut self, leaf: &T) -> bool {
+        leaf.key() > &self.0
+    }
+}
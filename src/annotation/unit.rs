@@ -4,6 +4,9 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+use crate::persistent::PersistentNStack;
+#[cfg(feature = "store")]
+use crate::store::StoredNStack;
 use crate::NStack;
 
 use ranno::Annotation;
@@ -11,3 +14,12 @@ use ranno::Annotation;
 impl<T, A> Annotation<NStack<T, A>> for () {
     fn from_child(_: &NStack<T, A>) -> Self {}
 }
+
+impl<T, A> Annotation<PersistentNStack<T, A>> for () {
+    fn from_child(_: &PersistentNStack<T, A>) -> Self {}
+}
+
+#[cfg(feature = "store")]
+impl<T, A> Annotation<StoredNStack<T, A>> for () {
+    fn from_child(_: &StoredNStack<T, A>) -> Self {}
+}
@@ -0,0 +1,377 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Zero-copy archived stacks, using `rkyv`.
+//!
+//! An archived stack stores its leaves in order, followed by the annotations
+//! of all of its blocks, level by level starting at the root. Every block
+//! also stores the span of its children, in the annotations for nodes and in
+//! the leaves for leaves, since blocks may be under-full.
+
+use crate::annotation::{ArchivedMaxKey, Keyed, MaxKey};
+use crate::{NStack, N};
+
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::cell::Ref;
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::Range;
+
+use rkyv::munge::munge;
+use rkyv::rancor::{Fallible, Source};
+use rkyv::ser::{Allocator, Writer};
+use rkyv::vec::{ArchivedVec, VecResolver};
+use rkyv::{Archive, Archived, Deserialize, Place, Portable, Serialize};
+
+use ranno::Annotation;
+
+/// An archived [`NStack`].
+#[derive(Portable, rkyv::bytecheck::CheckBytes)]
+#[bytecheck(crate = rkyv::bytecheck, verify)]
+#[repr(C)]
+pub struct ArchivedNStack<T, A>
+where
+    T: Archive,
+    A: Archive,
+{
+    height: Archived<u32>,
+    leaves: ArchivedVec<Archived<T>>,
+    annos: ArchivedVec<Archived<A>>,
+    spans: ArchivedVec<[Archived<u32>; 2]>,
+}
+
+/// The resolver for an archived [`NStack`].
+pub struct NStackResolver {
+    height: u32,
+    len: usize,
+    annos_len: usize,
+    leaves: VecResolver,
+    annos: VecResolver,
+    spans: VecResolver,
+}
+
+impl<T, A> Archive for NStack<T, A>
+where
+    T: Archive,
+    A: Archive,
+{
+    type Archived = ArchivedNStack<T, A>;
+    type Resolver = NStackResolver;
+
+    fn resolve(&self, resolver: Self::Resolver, out: Place<Self::Archived>) {
+        munge!(let ArchivedNStack { height, leaves, annos, spans } = out);
+        resolver.height.resolve((), height);
+        ArchivedVec::resolve_from_len(resolver.len, resolver.leaves, leaves);
+        ArchivedVec::resolve_from_len(
+            resolver.annos_len,
+            resolver.annos,
+            annos,
+        );
+        ArchivedVec::resolve_from_len(
+            resolver.annos_len,
+            resolver.spans,
+            spans,
+        );
+    }
+}
+
+impl<T, A, S> Serialize<S> for NStack<T, A>
+where
+    T: Serialize<S>,
+    A: Serialize<S> + Annotation<NStack<T, A>>,
+    S: Fallible + Allocator + Writer + ?Sized,
+{
+    fn serialize(
+        &self,
+        serializer: &mut S,
+    ) -> Result<Self::Resolver, S::Error> {
+        let root = A::from_child(self);
+
+        let mut leaves = Vec::new();
+        let mut annos: Vec<Ref<A>> = Vec::new();
+        let mut spans: Vec<[u32; 2]> = Vec::new();
+
+        // breadth first, skipping the empty blocks left behind by popping
+        let mut level = alloc::vec![self];
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            for block in level {
+                match block {
+                    NStack::Leaf(leaf) => {
+                        let first = leaves.len();
+                        leaves.extend(leaf.iter().flatten());
+                        spans.push([
+                            first as u32,
+                            (leaves.len() - first) as u32,
+                        ]);
+                    }
+                    NStack::Node(node) => {
+                        // the root annotation comes first
+                        let first = annos.len() + 1;
+                        for annotated in node.iter().flatten() {
+                            if !annotated.child().is_empty() {
+                                annos.push(annotated.anno());
+                                next.push(&**annotated.child());
+                            }
+                        }
+                        spans.push([
+                            first as u32,
+                            (annos.len() + 1 - first) as u32,
+                        ]);
+                    }
+                }
+            }
+            level = next;
+        }
+
+        let annos: Vec<&A> = core::iter::once(&root)
+            .chain(annos.iter().map(|a| &**a))
+            .collect();
+
+        Ok(NStackResolver {
+            height: self.height() as u32,
+            len: leaves.len(),
+            annos_len: annos.len(),
+            leaves: ArchivedVec::serialize_from_iter::<T, _, _>(
+                leaves.iter().copied(),
+                serializer,
+            )?,
+            annos: ArchivedVec::serialize_from_iter::<A, _, _>(
+                annos.iter().copied(),
+                serializer,
+            )?,
+            spans: ArchivedVec::serialize_from_iter::<[u32; 2], _, _>(
+                spans.iter(),
+                serializer,
+            )?,
+        })
+    }
+}
+
+impl<T, A, D> Deserialize<NStack<T, A>, D> for ArchivedNStack<T, A>
+where
+    T: Archive,
+    Archived<T>: Deserialize<T, D>,
+    A: Archive + Annotation<NStack<T, A>>,
+    D: Fallible + ?Sized,
+{
+    fn deserialize(
+        &self,
+        deserializer: &mut D,
+    ) -> Result<NStack<T, A>, D::Error> {
+        let mut stack = NStack::new();
+        for leaf in self.leaves.iter() {
+            stack.push(leaf.deserialize(deserializer)?);
+        }
+        Ok(stack)
+    }
+}
+
+impl<T, A> ArchivedNStack<T, A>
+where
+    T: Archive,
+    A: Archive,
+{
+    /// Returns the number of elements in the stack
+    pub fn len(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    /// Returns true if the stack has no elements
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Returns a reference to the `nth` element, if any
+    pub fn nth(&self, index: u64) -> Option<&Archived<T>> {
+        self.leaves.get(index as usize)
+    }
+
+    /// Iterate over the elements of the stack
+    pub fn iter(&self) -> core::slice::Iter<'_, Archived<T>> {
+        self.leaves.iter()
+    }
+
+    /// Returns the annotation of the whole stack
+    pub fn annotation(&self) -> &Archived<A> {
+        &self.annos[0]
+    }
+
+    /// Returns a reference to the element with the largest key, if any
+    pub fn max_key<K>(&self) -> Option<&Archived<T>>
+    where
+        K: Archive,
+        Archived<T>: Keyed<Archived<K>>,
+        Archived<A>: Borrow<Archived<MaxKey<K>>>,
+        Archived<K>: PartialOrd,
+    {
+        // the block of the current level holding the largest key, with the
+        // height bounded by the number of blocks when verified
+        let mut block = 0;
+
+        for _ in 1..self.height.to_native() {
+            let mut max = None;
+
+            for child in self.span(block) {
+                let anno = self.annos[child].borrow();
+                if max.is_none_or(|(_, max)| anno > max) {
+                    max = Some((child, anno));
+                }
+            }
+
+            block = max?.0;
+        }
+
+        let mut max: Option<&Archived<T>> = None;
+
+        for leaf in &self.leaves[self.span(block)] {
+            if max.is_none_or(|max| leaf.key() > max.key()) {
+                max = Some(leaf);
+            }
+        }
+
+        max
+    }
+
+    /// Returns the range of the children of a block
+    fn span(&self, block: usize) -> Range<usize> {
+        let [first, count] = self.spans[block];
+        let first = first.to_native() as usize;
+        first..first + count.to_native() as usize
+    }
+}
+
+impl<K> fmt::Debug for ArchivedMaxKey<K>
+where
+    K: Archive,
+    Archived<K>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchivedMaxKey::NegativeInfinity => write!(f, "NegativeInfinity"),
+            ArchivedMaxKey::Maximum(key) => {
+                f.debug_tuple("Maximum").field(key).finish()
+            }
+        }
+    }
+}
+
+impl<K> PartialEq for ArchivedMaxKey<K>
+where
+    K: Archive,
+    Archived<K>: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ArchivedMaxKey::Maximum(a), ArchivedMaxKey::Maximum(b)) => a == b,
+            (ArchivedMaxKey::NegativeInfinity, other) => {
+                matches!(other, ArchivedMaxKey::NegativeInfinity)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<K> PartialOrd for ArchivedMaxKey<K>
+where
+    K: Archive,
+    Archived<K>: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (ArchivedMaxKey::Maximum(a), ArchivedMaxKey::Maximum(b)) => {
+                a.partial_cmp(b)
+            }
+            (ArchivedMaxKey::Maximum(_), _) => Some(Ordering::Greater),
+            (_, ArchivedMaxKey::Maximum(_)) => Some(Ordering::Less),
+            _ => Some(Ordering::Equal),
+        }
+    }
+}
+
+/// The layout of an archived stack is inconsistent.
+#[derive(Debug)]
+pub struct InvalidLayout;
+
+impl fmt::Display for InvalidLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid archived nstack layout")
+    }
+}
+
+impl core::error::Error for InvalidLayout {}
+
+unsafe impl<T, A, C> rkyv::bytecheck::Verify<C> for ArchivedNStack<T, A>
+where
+    T: Archive,
+    A: Archive,
+    C: Fallible + ?Sized,
+    C::Error: Source,
+{
+    fn verify(&self, _: &mut C) -> Result<(), C::Error> {
+        let height = self.height.to_native();
+        let blocks = self.annos.len();
+
+        if height == 0 || blocks == 0 || self.spans.len() != blocks {
+            return Err(Source::new(InvalidLayout));
+        }
+        // every level holds at least one block
+        if height as usize > blocks {
+            return Err(Source::new(InvalidLayout));
+        }
+
+        // every level of nodes must span the next level, in order
+        let mut level = 0..1;
+        for _ in 1..height {
+            let mut next = level.end;
+            for block in level.clone() {
+                let span = self.span(block);
+                if span.start != next || span.len() > N || span.end > blocks {
+                    return Err(Source::new(InvalidLayout));
+                }
+                next = span.end;
+            }
+            level = level.end..next;
+
+            if level.is_empty() {
+                return Err(Source::new(InvalidLayout));
+            }
+        }
+
+        // and the level of leaves all the elements
+        if level.end != blocks {
+            return Err(Source::new(InvalidLayout));
+        }
+        let mut next = 0;
+        for block in level {
+            let span = self.span(block);
+            if span.start != next || span.len() > N {
+                return Err(Source::new(InvalidLayout));
+            }
+            next = span.end;
+        }
+        if next != self.leaves.len() {
+            return Err(Source::new(InvalidLayout));
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, A> NStack<T, A> {
+    /// Returns true if the block holds no leaves
+    fn is_empty(&self) -> bool {
+        match self {
+            NStack::Leaf(leaf) => leaf[0].is_none(),
+            NStack::Node(node) => match &node[0] {
+                Some(annotated) => annotated.child().is_empty(),
+                None => true,
+            },
+        }
+    }
+}
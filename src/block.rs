@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Access to the blocks of the stacks laid out like the
+//! [`NStack`](crate::NStack).
+//!
+//! The annotations are computed from the [`Block`]s of any such stack, and
+//! the queries over them are provided by the [`Blocks`] trait.
+
+use crate::annotation::{
+    Bloom, Cardinality, FindKey, FindMaxKey, Index, Keyed, MaxKey,
+};
+use crate::N;
+
+use alloc::boxed::Box;
+
+use core::borrow::Borrow;
+use core::hash::Hash;
+use core::ptr;
+
+use microkelvin::{Branch, BranchMut, Compound};
+use ranno::{Annotated, Annotation};
+
+/// A block of a stack laid out like an [`NStack`](crate::NStack).
+pub enum Block<'a, T, A, C> {
+    /// A leaf block, holding elements
+    Leaf(&'a [Option<T>; N]),
+    /// A node block, holding annotated children
+    Node(&'a [Option<Annotated<Box<C>, A>>; N]),
+    /// A block yet to be loaded, known only by its annotation
+    Stored(&'a A),
+}
+
+/// A stack laid out like an [`NStack`](crate::NStack), giving access to its
+/// blocks.
+pub trait Blocks<T, A>: Sized {
+    /// Returns the root block of the stack
+    fn block(&self) -> Block<'_, T, A, Self>;
+
+    /// Returns true if both stacks are known to share their root block, and
+    /// so hold the same elements
+    fn shares(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+
+    /// Construct a [`Branch`] pointing to the `nth` element, if any
+    fn nth(&self, index: u64) -> Option<Branch<'_, Self, A>>
+    where
+        Self: Compound<A>,
+        A: Annotation<Self> + Borrow<Cardinality>,
+    {
+        Branch::walk(self, Index(index))
+    }
+
+    /// Construct a [`BranchMut`] pointing to the `nth` element, if any
+    fn nth_mut(&mut self, index: u64) -> Option<BranchMut<'_, Self, A>>
+    where
+        Self: Compound<A>,
+        A: Annotation<Self> + Borrow<Cardinality>,
+    {
+        BranchMut::walk(self, Index(index))
+    }
+
+    /// Construct a [`Branch`] pointing to the element with the largest key
+    fn max_key<K>(&self) -> Option<Branch<'_, Self, A>>
+    where
+        Self: Compound<A>,
+        Self::Leaf: Keyed<K>,
+        A: Annotation<Self> + Borrow<MaxKey<K>>,
+        K: Clone + PartialOrd,
+    {
+        Branch::walk(self, FindMaxKey::<K>::default())
+    }
+
+    /// Construct a [`BranchMut`] pointing to the element with the largest key
+    fn max_key_mut<K>(&mut self) -> Option<BranchMut<'_, Self, A>>
+    where
+        Self: Compound<A>,
+        Self::Leaf: Keyed<K>,
+        A: Annotation<Self> + Borrow<MaxKey<K>>,
+        K: Clone + PartialOrd,
+    {
+        BranchMut::walk(self, FindMaxKey::<K>::default())
+    }
+
+    /// Returns true if the stack contains an element with the given key
+    fn contains_key<K>(&self, key: &K) -> bool
+    where
+        Self: Compound<A>,
+        Self::Leaf: Keyed<K>,
+        A: Annotation<Self> + Borrow<Bloom<K>>,
+        K: Hash + PartialEq,
+    {
+        self.find_key(key).is_some()
+    }
+
+    /// Construct a [`Branch`] pointing to the first element with the given
+    /// key, if any
+    fn find_key<K>(&self, key: &K) -> Option<Branch<'_, Self, A>>
+    where
+        Self: Compound<A>,
+        Self::Leaf: Keyed<K>,
+        A: Annotation<Self> + Borrow<Bloom<K>>,
+        K: Hash + PartialEq,
+    {
+        Branch::walk(self, FindKey(key))
+    }
+
+    /// Construct a [`BranchMut`] pointing to the first element with the given
+    /// key, if any
+    fn find_key_mut<K>(&mut self, key: &K) -> Option<BranchMut<'_, Self, A>>
+    where
+        Self: Compound<A>,
+        Self::Leaf: Keyed<K>,
+        A: Annotation<Self> + Borrow<Bloom<K>>,
+        K: Hash + PartialEq,
+    {
+        BranchMut::walk(self, FindKey(key))
+    }
+}
@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Checkpoints and rollback for speculative mutation.
+
+use crate::annotation::Cardinality;
+use crate::NStack;
+
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::ops::Deref;
+
+use microkelvin::BranchMut;
+use ranno::Annotation;
+
+/// Identifies a checkpoint taken with [`Checkpointed::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CheckpointId(u64);
+
+/// The inverse of an edit made to the stack.
+#[derive(Debug)]
+enum Undo<T> {
+    /// Pop the pushed element, collapsing the root if the push grew it
+    Push { grew: bool },
+    /// Push back the popped element
+    Pop(T),
+    /// Write back the previous value of the element at the index
+    Write(u64, T),
+}
+
+/// An [`NStack`] that can roll back to previously taken checkpoints.
+///
+/// While a checkpoint is active every edit records its inverse in an undo
+/// log, so rolling back restores the exact prior tree without ever cloning
+/// it. When no checkpoint is active no log is kept.
+#[derive(Debug)]
+pub struct Checkpointed<T, A> {
+    stack: NStack<T, A>,
+    log: Vec<Undo<T>>,
+    // id and length of the log at each active checkpoint
+    checkpoints: Vec<(CheckpointId, usize)>,
+    // ids are never reused, so released ones are always recognized
+    next_id: u64,
+}
+
+impl<T, A> Checkpointed<T, A> {
+    /// Creates a new empty Checkpointed stack
+    pub const fn new() -> Self {
+        Self {
+            stack: NStack::new(),
+            log: Vec::new(),
+            checkpoints: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Take a checkpoint of the current state of the stack.
+    ///
+    /// Checkpoints nest, and rolling back to or committing a checkpoint also
+    /// releases all checkpoints taken after it.
+    pub fn checkpoint(&mut self) -> CheckpointId {
+        let id = CheckpointId(self.next_id);
+        self.next_id += 1;
+
+        self.checkpoints.push((id, self.log.len()));
+        id
+    }
+
+    /// Release the checkpoint, keeping all edits made since it was taken.
+    ///
+    /// # Panics
+    /// If the checkpoint was already released.
+    pub fn commit(&mut self, id: CheckpointId) {
+        self.release(id);
+
+        if self.checkpoints.is_empty() {
+            self.log.clear();
+        }
+    }
+
+    /// Consume the structure, returning the inner stack
+    pub fn into_inner(self) -> NStack<T, A> {
+        self.stack
+    }
+
+    fn is_recording(&self) -> bool {
+        !self.checkpoints.is_empty()
+    }
+
+    fn release(&mut self, id: CheckpointId) -> usize {
+        // active checkpoints are ordered by id
+        let index = self
+            .checkpoints
+            .binary_search_by_key(&id, |(id, _)| *id)
+            .expect("checkpoint was already released");
+        let (_, len) = self.checkpoints[index];
+        self.checkpoints.truncate(index);
+        len
+    }
+}
+
+impl<T, A> Checkpointed<T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    /// Pushes a new element onto the stack
+    pub fn push(&mut self, t: T) {
+        if !self.is_recording() {
+            return self.stack.push(t);
+        }
+
+        let height = self.stack.height();
+        self.stack.push(t);
+        let grew = self.stack.height() > height;
+
+        self.log.push(Undo::Push { grew });
+    }
+
+    /// Pop an element off the stack.
+    ///
+    /// Returns the popped element, if any.
+    pub fn pop(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let t = self.stack.pop()?;
+
+        if self.is_recording() {
+            self.log.push(Undo::Pop(t.clone()));
+        }
+
+        Some(t)
+    }
+
+    /// Restore the stack to the state it had when the checkpoint was taken,
+    /// releasing it.
+    ///
+    /// # Panics
+    /// If the checkpoint was already released.
+    pub fn rollback(&mut self, id: CheckpointId)
+    where
+        A: Borrow<Cardinality>,
+    {
+        let len = self.release(id);
+
+        for undo in self.log.drain(len..).rev() {
+            match undo {
+                Undo::Push { grew } => {
+                    self.stack.pop();
+                    if grew {
+                        self.stack.shrink();
+                    }
+                }
+                Undo::Pop(t) => self.stack.push(t),
+                Undo::Write(index, t) => {
+                    if let Some(mut branch) = self.stack.nth_mut(index) {
+                        *branch = t;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T, A> Checkpointed<T, A>
+where
+    T: Clone,
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    /// Construct a [`BranchMut`] pointing to the `nth` element, if any
+    pub fn nth_mut(
+        &mut self,
+        index: u64,
+    ) -> Option<BranchMut<'_, NStack<T, A>, A>> {
+        let recording = self.is_recording();
+        let branch = self.stack.nth_mut(index)?;
+
+        if recording {
+            self.log.push(Undo::Write(index, (*branch).clone()));
+        }
+
+        Some(branch)
+    }
+}
+
+impl<T, A> NStack<T, A> {
+    /// Replace a root with only its first child by that child
+    fn shrink(&mut self) {
+        if let NStack::Node([first @ Some(_), None, None, None]) = self {
+            if let Some(annotated) = first.take() {
+                let (child, _) = annotated.split();
+                *self = *child;
+            }
+        }
+    }
+}
+
+impl<T, A> Deref for Checkpointed<T, A> {
+    type Target = NStack<T, A>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stack
+    }
+}
+
+impl<T, A> Default for Checkpointed<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A> From<NStack<T, A>> for Checkpointed<T, A> {
+    fn from(stack: NStack<T, A>) -> Self {
+        Self {
+            stack,
+            log: Vec::new(),
+            checkpoints: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
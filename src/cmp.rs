@@ -0,0 +1,203 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Comparison and hashing of stacks as sequences of elements.
+//!
+//! Two stacks compare equal when they hold equal elements in the same order,
+//! whatever their shape or annotation type. Ordering is lexicographic, and
+//! hashing covers every element and then the length.
+
+use crate::annotation::{Cardinality, Digest};
+use crate::block::{Block, Blocks};
+use crate::persistent::PersistentNStack;
+use crate::NStack;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ptr;
+
+use ranno::Annotation;
+
+impl<T, A, B> PartialEq<NStack<T, B>> for NStack<T, A>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &NStack<T, B>) -> bool {
+        ptr::eq(
+            self as *const _ as *const (),
+            other as *const _ as *const (),
+        ) || self.iter().eq(other.iter())
+    }
+}
+
+impl<T, A> Eq for NStack<T, A> where T: Eq {}
+
+impl<T, A, B> PartialOrd<NStack<T, B>> for NStack<T, A>
+where
+    T: PartialOrd,
+{
+    fn partial_cmp(&self, other: &NStack<T, B>) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T, A> Ord for NStack<T, A>
+where
+    T: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T, A> Hash for NStack<T, A>
+where
+    T: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_elements(self.iter(), state);
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    T: PartialEq,
+    A: Annotation<Self> + Borrow<Cardinality> + Digest,
+{
+    /// Returns true if both stacks hold the same elements, skipping the
+    /// subtrees whose annotations are [`Digest`] equal, as [`NStack::diff`]
+    /// does.
+    pub fn digest_eq(&self, other: &Self) -> bool {
+        self.diff(other).next().is_none()
+    }
+}
+
+impl<T, A> PersistentNStack<T, A>
+where
+    T: Clone + PartialEq,
+    A: Annotation<Self> + Borrow<Cardinality> + Digest,
+{
+    /// Returns true if both stacks hold the same elements, skipping the
+    /// subtrees shared by both stacks or whose annotations are [`Digest`]
+    /// equal, as [`PersistentNStack::diff`] does.
+    pub fn digest_eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.diff(other).next().is_none()
+    }
+}
+
+impl<T, A> PartialEq for PersistentNStack<T, A>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || elements(self).eq(elements(other))
+    }
+}
+
+impl<T, A> Eq for PersistentNStack<T, A> where T: Eq {}
+
+impl<T, A> PartialOrd for PersistentNStack<T, A>
+where
+    T: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.ptr_eq(other) {
+            return Some(Ordering::Equal);
+        }
+        elements(self).partial_cmp(elements(other))
+    }
+}
+
+impl<T, A> Ord for PersistentNStack<T, A>
+where
+    T: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.ptr_eq(other) {
+            return Ordering::Equal;
+        }
+        elements(self).cmp(elements(other))
+    }
+}
+
+impl<T, A> Hash for PersistentNStack<T, A>
+where
+    T: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_elements(elements(self), state);
+    }
+}
+
+/// Hash every element and then their number, in a single pass
+fn hash_elements<'a, T, H>(elements: impl Iterator<Item = &'a T>, state: &mut H)
+where
+    T: 'a + Hash,
+    H: Hasher,
+{
+    let mut len = 0;
+    for t in elements {
+        t.hash(state);
+        len += 1;
+    }
+    state.write_usize(len);
+}
+
+/// Iterate over the elements of a stack through its blocks, which unlike
+/// walking it requires no annotation
+fn elements<T, A, S>(stack: &S) -> Elements<'_, T, A, S>
+where
+    S: Blocks<T, A>,
+{
+    Elements {
+        path: vec![(stack, 0)],
+        _marker: PhantomData,
+    }
+}
+
+struct Elements<'a, T, A, S> {
+    // blocks from the root to the current leaf, and the next slot index
+    path: Vec<(&'a S, usize)>,
+    _marker: PhantomData<(&'a T, A)>,
+}
+
+impl<'a, T, A, S> Iterator for Elements<'a, T, A, S>
+where
+    S: Blocks<T, A>,
+    A: 'a,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (stack, i) = self.path.last_mut()?;
+            let (stack, slot) = (*stack, *i);
+            *i += 1;
+
+            match stack.block() {
+                Block::Leaf(leaf) => {
+                    if let Some(Some(t)) = leaf.get(slot) {
+                        return Some(t);
+                    }
+                }
+                Block::Node(node) => {
+                    if let Some(Some(annotated)) = node.get(slot) {
+                        self.path.push((annotated.child(), 0));
+                        continue;
+                    }
+                }
+                Block::Stored(_) => (),
+            }
+
+            self.path.pop();
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Cursor for sequential access and in-place edits.
+
+use crate::annotation::Cardinality;
+use crate::{NStack, NStackRef, N};
+
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+
+use ranno::{Annotated, Annotation};
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self> + Borrow<Cardinality>,
+{
+    /// Construct a [`CursorMut`] pointing to the first element of the stack
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, A> {
+        let len = **A::from_child(self).borrow();
+
+        let mut cursor = CursorMut {
+            root: self,
+            path: Vec::new(),
+            index: 0,
+            position: 0,
+            len,
+        };
+
+        if len > 0 {
+            cursor.descend(0);
+        }
+
+        cursor
+    }
+}
+
+/// A cursor over the elements of an [`NStack`] with mutable access.
+///
+/// The cursor keeps the path from the root to the current leaf detached from
+/// the tree, so moving to a neighbouring element only touches the nodes
+/// between the two. The annotations of the nodes the cursor moved through
+/// are recomputed lazily, when next requested. The path is re-attached when
+/// the cursor is dropped.
+pub struct CursorMut<'a, T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    root: &'a mut NStack<T, A>,
+    // subtrees detached from their parents, and their index in the parent
+    path: Vec<(usize, NStackRef<T, A>)>,
+    // index into the current leaf
+    index: usize,
+    position: u64,
+    len: u64,
+}
+
+impl<'a, T, A> CursorMut<'a, T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    /// Returns the index of the element the cursor points to
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the number of elements in the stack
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the stack has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the current element, if any
+    pub fn current(&self) -> Option<&T> {
+        match self.block() {
+            NStack::Leaf(leaf) => leaf[self.index].as_ref(),
+            NStack::Node(_) => None,
+        }
+    }
+
+    /// Returns a mutable reference to the current element, if any
+    pub fn current_mut(&mut self) -> Option<&mut T> {
+        let index = self.index;
+        match self.block_mut() {
+            NStack::Leaf(leaf) => leaf[index].as_mut(),
+            NStack::Node(_) => None,
+        }
+    }
+
+    fn block(&self) -> &NStack<T, A> {
+        match self.path.last() {
+            Some((_, block)) => block,
+            None => self.root,
+        }
+    }
+
+    fn block_mut(&mut self) -> &mut NStack<T, A> {
+        match self.path.last_mut() {
+            Some((_, block)) => block,
+            None => self.root,
+        }
+    }
+
+    /// Re-attach the current block to its parent, making the parent the
+    /// current block. Returns the index of the re-attached block.
+    fn ascend(&mut self) -> Option<usize> {
+        let (i, child) = self.path.pop()?;
+        match self.block_mut() {
+            NStack::Node(node) => node[i] = Some(Annotated::new(child)),
+            NStack::Leaf(_) => unreachable!(),
+        }
+        Some(i)
+    }
+}
+
+impl<'a, T, A> CursorMut<'a, T, A>
+where
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    /// Move the cursor to the element at `index`, returning a mutable
+    /// reference to it.
+    ///
+    /// Returns `None` and leaves the cursor in place if `index` is out of
+    /// bounds.
+    pub fn seek(&mut self, index: u64) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+
+        while self.ascend().is_some() {}
+        self.descend(index);
+        self.position = index;
+
+        self.current_mut()
+    }
+
+    /// Move the cursor to the next element, returning a mutable reference to
+    /// it.
+    ///
+    /// Returns `None` and leaves the cursor in place if the cursor is at the
+    /// last element.
+    // the returned reference borrows the cursor, so it can't be an `Iterator`
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&mut T> {
+        if self.position + 1 >= self.len {
+            return None;
+        }
+        self.position += 1;
+
+        let index = self.index + 1;
+        if let NStack::Leaf(leaf) = self.block() {
+            if index < N && leaf[index].is_some() {
+                self.index = index;
+                return self.current_mut();
+            }
+        }
+
+        loop {
+            let i = self.ascend().expect("next element exists") + 1;
+            if let NStack::Node(node) = self.block() {
+                if i < N && node[i].is_some() {
+                    self.take_child(i);
+                    self.descend(0);
+                    return self.current_mut();
+                }
+            }
+        }
+    }
+
+    /// Move the cursor to the previous element, returning a mutable
+    /// reference to it.
+    ///
+    /// Returns `None` and leaves the cursor in place if the cursor is at the
+    /// first element.
+    pub fn prev(&mut self) -> Option<&mut T> {
+        if self.position == 0 || self.len == 0 {
+            return None;
+        }
+        self.position -= 1;
+
+        if self.index > 0 {
+            self.index -= 1;
+            return self.current_mut();
+        }
+
+        loop {
+            let i = self.ascend().expect("previous element exists");
+            if i > 0 {
+                let c = self.take_child(i - 1);
+                self.descend(c - 1);
+                return self.current_mut();
+            }
+        }
+    }
+
+    /// Descend from the current block to the element at `index` in it
+    fn descend(&mut self, mut index: u64) {
+        loop {
+            let i = match self.block() {
+                NStack::Leaf(_) => {
+                    self.index = index as usize;
+                    return;
+                }
+                NStack::Node(node) => {
+                    let mut found = None;
+                    for (i, annotated) in node.iter().enumerate() {
+                        if let Some(annotated) = annotated {
+                            let c = **(*annotated.anno()).borrow();
+                            if index < c {
+                                found = Some(i);
+                                break;
+                            }
+                            index -= c;
+                        }
+                    }
+                    found.expect("index within bounds")
+                }
+            };
+            self.take_child(i);
+        }
+    }
+
+    /// Detach the `i`th child of the current block, making it the current
+    /// block. Returns its cardinality.
+    fn take_child(&mut self, i: usize) -> u64 {
+        let annotated = match self.block_mut() {
+            NStack::Node(node) => node[i].take().expect("child exists"),
+            NStack::Leaf(_) => unreachable!(),
+        };
+
+        let c = **(*annotated.anno()).borrow();
+
+        let (child, _) = annotated.split();
+        self.path.push((i, child));
+
+        c
+    }
+}
+
+impl<'a, T, A> Drop for CursorMut<'a, T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    fn drop(&mut self) {
+        while self.ascend().is_some() {}
+    }
+}
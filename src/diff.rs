@@ -0,0 +1,246 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Structural diff between two stacks.
+//!
+//! Both stacks are walked in lockstep, element by element, so their shapes
+//! need not match. Whenever both walks reach the start of a subtree at the
+//! same index, and the subtrees are shared or [`Digest`] equal, they are
+//! skipped as a whole, using their [`Cardinality`] to move past them.
+
+use crate::annotation::{Cardinality, Digest};
+use crate::block::Blocks;
+use crate::persistent::PersistentNStack;
+use crate::NStack;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::marker::PhantomData;
+
+use microkelvin::{Child, Compound};
+use ranno::{Annotated, Annotation};
+
+impl<T, A> NStack<T, A>
+where
+    T: PartialEq,
+    A: Annotation<Self> + Borrow<Cardinality> + Digest,
+{
+    /// Iterate over the differences between this stack and `other`, in
+    /// increasing index order.
+    ///
+    /// The index of the first entry is the length of the common prefix of
+    /// both stacks, and any entries for elements present in only one of them
+    /// come last.
+    ///
+    /// Subtrees starting at the same index in both stacks, with the same
+    /// length and [`Digest`] equal annotations, are skipped without
+    /// descending into them.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, Self, A> {
+        Diff::new(self, other)
+    }
+}
+
+impl<T, A> PersistentNStack<T, A>
+where
+    T: Clone + PartialEq,
+    A: Annotation<Self> + Borrow<Cardinality> + Digest,
+{
+    /// Iterate over the differences between this stack and `other`, in
+    /// increasing index order.
+    ///
+    /// The index of the first entry is the length of the common prefix of
+    /// both stacks, and any entries for elements present in only one of them
+    /// come last.
+    ///
+    /// Subtrees starting at the same index in both stacks are skipped
+    /// without descending into them if they are shared, or have the same
+    /// length and [`Digest`] equal annotations.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, Self, A> {
+        Diff::new(self, other)
+    }
+}
+
+/// A difference between two stacks, as reported by [`Diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffEntry<'a, T> {
+    /// The element at `index` differs between the two stacks
+    Changed { index: u64, old: &'a T, new: &'a T },
+    /// The element at `index` is only present in the other stack
+    Appended { index: u64, new: &'a T },
+    /// The element at `index` is only present in this stack
+    Truncated { index: u64, old: &'a T },
+}
+
+impl<'a, T> DiffEntry<'a, T> {
+    /// Returns the index of the differing element
+    pub fn index(&self) -> u64 {
+        match self {
+            DiffEntry::Changed { index, .. }
+            | DiffEntry::Appended { index, .. }
+            | DiffEntry::Truncated { index, .. } => *index,
+        }
+    }
+}
+
+/// The blocks on the path to the next slot of a stack, each with the index
+/// of the slot to visit next
+type Path<'a, C> = Vec<(&'a C, usize)>;
+
+/// Iterator over the differences between two stacks.
+///
+/// Created by [`NStack::diff`] and [`PersistentNStack::diff`].
+pub struct Diff<'a, C, A>
+where
+    C: Compound<A>,
+{
+    old: Path<'a, C>,
+    new: Path<'a, C>,
+    // index of the next element of both stacks
+    index: u64,
+    _marker: PhantomData<A>,
+}
+
+impl<'a, C, A> Diff<'a, C, A>
+where
+    C: Compound<A>,
+    A: 'a + Annotation<C>,
+{
+    fn new(old: &'a C, new: &'a C) -> Self {
+        Self {
+            old: vec![(old, 0)],
+            new: vec![(new, 0)],
+            index: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, C, A> Iterator for Diff<'a, C, A>
+where
+    C: Compound<A> + Blocks<C::Leaf, A>,
+    C::Leaf: PartialEq,
+    A: 'a + Annotation<C> + Borrow<Cardinality> + Digest,
+{
+    type Item = DiffEntry<'a, C::Leaf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let index = self.index;
+
+            let (old, new) = match (peek(&mut self.old), peek(&mut self.new)) {
+                (None, None) => return None,
+                (Some(Child::Leaf(old)), Some(Child::Leaf(new))) => (old, new),
+                (Some(Child::Node(old)), Some(Child::Node(new)))
+                    if skippable(old, new) =>
+                {
+                    advance(&mut self.old);
+                    advance(&mut self.new);
+                    self.index += len(old);
+                    continue;
+                }
+                (old, new) => {
+                    // descend until both stacks are at an element, or one
+                    // of them is exhausted
+                    let mut descended = false;
+                    if let Some(Child::Node(old)) = old {
+                        descend(&mut self.old, old);
+                        descended = true;
+                    }
+                    if let Some(Child::Node(new)) = new {
+                        descend(&mut self.new, new);
+                        descended = true;
+                    }
+                    if descended {
+                        continue;
+                    }
+
+                    self.index += 1;
+                    return match (old, new) {
+                        (Some(Child::Leaf(old)), _) => {
+                            advance(&mut self.old);
+                            Some(DiffEntry::Truncated { index, old })
+                        }
+                        (_, Some(Child::Leaf(new))) => {
+                            advance(&mut self.new);
+                            Some(DiffEntry::Appended { index, new })
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+            };
+
+            advance(&mut self.old);
+            advance(&mut self.new);
+            self.index += 1;
+
+            if old != new {
+                return Some(DiffEntry::Changed { index, old, new });
+            }
+        }
+    }
+}
+
+/// Returns the next child on the path, either a leaf or a node, or `None`
+/// if the stack is exhausted
+fn peek<'a, C, A>(path: &mut Path<'a, C>) -> Option<Child<'a, C, A>>
+where
+    C: Compound<A>,
+{
+    loop {
+        let (block, i) = *path.last()?;
+        match block.child(i) {
+            Child::EndOfNode => {
+                path.pop();
+            }
+            Child::Empty => advance(path),
+            child => return Some(child),
+        }
+    }
+}
+
+/// Move past the next child on the path
+fn advance<C>(path: &mut Path<'_, C>) {
+    if let Some((_, i)) = path.last_mut() {
+        *i += 1;
+    }
+}
+
+/// Move into the next child on the path
+fn descend<'a, C, A>(path: &mut Path<'a, C>, node: &'a Annotated<Box<C>, A>)
+where
+    C: Compound<A>,
+    A: Annotation<C>,
+{
+    advance(path);
+    path.push((node.child(), 0));
+}
+
+/// Returns the number of elements in a subtree
+fn len<C, A>(node: &Annotated<Box<C>, A>) -> u64
+where
+    A: Annotation<C> + Borrow<Cardinality>,
+{
+    **Borrow::<Cardinality>::borrow(&*node.anno())
+}
+
+/// Returns true if two subtrees starting at the same index hold the same
+/// elements, as far as can be told without descending into them
+fn skippable<C, A>(
+    old: &Annotated<Box<C>, A>,
+    new: &Annotated<Box<C>, A>,
+) -> bool
+where
+    C: Compound<A> + Blocks<C::Leaf, A>,
+    A: Annotation<C> + Borrow<Cardinality> + Digest,
+{
+    if old.child().shares(new.child()) {
+        return true;
+    }
+    len(old) == len(new) && old.anno().digest_eq(&new.anno())
+}
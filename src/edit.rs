@@ -0,0 +1,449 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Editing elements at arbitrary positions of a stack.
+//!
+//! Inserting and removing in the middle of a stack relaxes its shape: blocks
+//! other than the last of a node may be under-full. Every block stays
+//! left-packed and every leaf stays at the same depth, and blocks are split
+//! and merged like in a B-tree so the height stays logarithmic.
+
+use crate::annotation::Cardinality;
+use crate::{NStack, NStackRef, N};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+
+use ranno::{Annotated, Annotation};
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self>,
+{
+    /// Retains only the elements for which `f` returns true, preserving
+    /// their order.
+    ///
+    /// The stack is re-packed from the remaining elements, so it is left at
+    /// its minimal height.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        // decide before taking the elements, so a panicking `f` leaves the
+        // stack untouched
+        let keep: Vec<bool> = self.iter().map(&mut f).collect();
+
+        let elements = self.take_elements();
+        let kept = elements
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(t, k)| k.then_some(t));
+        for t in kept {
+            self.push(t);
+        }
+    }
+
+    /// Removes the elements in `range` from the stack, returning them in
+    /// order.
+    ///
+    /// The remaining elements keep their order, and the stack is re-packed
+    /// from them, so it is left at its minimal height. The elements are
+    /// removed even if the returned iterator is not consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if its
+    /// end is greater than the length of the stack.
+    pub fn drain<R>(&mut self, range: R) -> impl Iterator<Item = T>
+    where
+        R: RangeBounds<u64>,
+    {
+        let len = self.iter().count();
+
+        // check the bounds before taking the elements, so a bad range leaves
+        // the stack untouched
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i.checked_add(1).unwrap_or_else(|| {
+                panic!("attempted to drain from after u64::MAX")
+            }),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i.checked_add(1).unwrap_or_else(|| {
+                panic!("attempted to drain up to u64::MAX inclusive")
+            }),
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => len as u64,
+        };
+        let (start, end) = (drain_index(start), drain_index(end));
+        assert!(
+            start <= end,
+            "drain start (is {start}) should be <= end (is {end})"
+        );
+        assert!(
+            end <= len,
+            "drain end (is {end}) should be <= len (is {len})"
+        );
+
+        let mut elements = self.take_elements();
+        let drained: Vec<T> = elements.drain(start..end).collect();
+
+        self.extend_from(elements);
+        drained.into_iter()
+    }
+
+    /// Take all elements out of the stack, leaving it empty
+    fn take_elements(&mut self) -> Vec<T> {
+        let mut elements = Vec::new();
+        mem::take(self).drain_into(&mut elements);
+        elements
+    }
+
+    fn extend_from(&mut self, elements: Vec<T>) {
+        for t in elements {
+            self.push(t);
+        }
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self> + Borrow<Cardinality>,
+{
+    /// Removes the element at `index` and returns it, replacing it with the
+    /// last element of the stack.
+    ///
+    /// Does not preserve the order of the elements, but only touches the
+    /// paths to `index` and to the last element. Returns `None` if `index`
+    /// is out of bounds.
+    pub fn swap_remove(&mut self, index: u64) -> Option<T> {
+        let len = **A::from_child(self).borrow();
+        if index >= len {
+            return None;
+        }
+
+        let last = self.pop()?;
+        if index == len - 1 {
+            return Some(last);
+        }
+
+        let mut branch = self.nth_mut(index)?;
+        Some(mem::replace(&mut *branch, last))
+    }
+
+    /// Inserts an element at position `index`, shifting all elements after
+    /// it to the right.
+    ///
+    /// Full blocks on the path to `index` are split, leaving under-full
+    /// blocks behind. Inserting at the end is the same as [`push`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length of the stack.
+    ///
+    /// [`push`]: NStack::push
+    pub fn insert(&mut self, index: u64, t: T) {
+        let len = **A::from_child(self).borrow();
+        assert!(
+            index <= len,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            len
+        );
+
+        if index == len {
+            return self.push(t);
+        }
+
+        if let Insert::Split(sibling) = self._insert(index, t) {
+            let old_root = mem::take(self);
+            *self = NStack::Node([
+                Some(Annotated::new(Box::new(old_root))),
+                Some(Annotated::new(Box::new(sibling))),
+                None,
+                None,
+            ]);
+        }
+    }
+
+    fn _insert(&mut self, index: u64, t: T) -> Insert<T, A> {
+        match self {
+            NStack::Leaf(leaf) => match insert_slot(leaf, index as usize, t) {
+                Some(right) => Insert::Split(NStack::Leaf(right)),
+                None => Insert::Ok,
+            },
+            NStack::Node(node) => {
+                let (i, offset) = locate(node, index, true);
+
+                let annotated = node[i].as_mut().expect("child exists");
+                let sibling =
+                    match annotated.child_mut()._insert(index - offset, t) {
+                        Insert::Ok => return Insert::Ok,
+                        Insert::Split(sibling) => sibling,
+                    };
+
+                let sibling = Annotated::new(Box::new(sibling));
+                match insert_slot(node, i + 1, sibling) {
+                    Some(right) => Insert::Split(NStack::Node(right)),
+                    None => Insert::Ok,
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the element at position `index`, shifting all
+    /// elements after it to the left.
+    ///
+    /// Blocks left with fewer than half their slots occupied are merged
+    /// with, or take an entry from, a sibling. Removing the last element is
+    /// the same as [`pop`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// [`pop`]: NStack::pop
+    pub fn remove(&mut self, index: u64) -> T {
+        let len = **A::from_child(self).borrow();
+        assert!(
+            index < len,
+            "removal index (is {}) should be < len (is {})",
+            index,
+            len
+        );
+
+        if index + 1 == len {
+            return self.pop().expect("index within bounds");
+        }
+
+        let t = self._remove(index);
+
+        // a root left with a single child is replaced by it
+        loop {
+            let child = match self {
+                NStack::Node(node) if node[1].is_none() => {
+                    match node[0].take() {
+                        Some(annotated) => annotated.split().0,
+                        None => break,
+                    }
+                }
+                _ => break,
+            };
+            *self = *child;
+        }
+
+        t
+    }
+
+    /// Pushes an element to the front of the stack, before all others.
+    ///
+    /// Only the blocks along the left edge are touched, splitting them when
+    /// full, as with [`insert`] at index 0.
+    ///
+    /// [`insert`]: NStack::insert
+    pub fn push_front(&mut self, t: T) {
+        self.insert(0, t)
+    }
+
+    /// Pops the element at the front of the stack.
+    ///
+    /// Only the blocks along the left edge are touched, as with [`remove`]
+    /// at index 0, so blocks emptied at the front are merged away and the
+    /// stack can be used as a queue. Returns the popped element, if any.
+    ///
+    /// [`remove`]: NStack::remove
+    pub fn pop_front(&mut self) -> Option<T> {
+        let len = **A::from_child(self).borrow();
+        match len {
+            0 => None,
+            _ => Some(self.remove(0)),
+        }
+    }
+
+    fn _remove(&mut self, index: u64) -> T {
+        match self {
+            NStack::Leaf(leaf) => {
+                let i = index as usize;
+                let t = leaf[i].take().expect("index within bounds");
+                leaf[i..].rotate_left(1);
+                t
+            }
+            NStack::Node(node) => {
+                let (i, offset) = locate(node, index, false);
+
+                let annotated = node[i].as_mut().expect("child exists");
+                let mut child = annotated.child_mut();
+                let t = child._remove(index - offset);
+                let underfull = child.occupied() < MIN;
+
+                if underfull {
+                    rebalance(node, i);
+                }
+
+                t
+            }
+        }
+    }
+}
+
+/// Converts a bound of a drained range to an index into the elements
+fn drain_index(i: u64) -> usize {
+    usize::try_from(i).unwrap_or_else(|_| {
+        panic!(
+            "drain bound (is {i}) should fit in usize (is {})",
+            usize::MAX
+        )
+    })
+}
+
+/// Minimum number of occupied slots a block is left with after a removal,
+/// if it has a sibling
+pub(crate) const MIN: usize = N / 2;
+
+type Children<T, A> = [Option<Annotated<NStackRef<T, A>, A>>; N];
+
+enum Insert<T, A> {
+    Ok,
+    /// The block was full and has been split, with the given block to be
+    /// inserted as its right sibling
+    Split(NStack<T, A>),
+}
+
+impl<T, A> NStack<T, A> {
+    /// Returns the number of occupied slots of the block
+    fn occupied(&self) -> usize {
+        match self {
+            NStack::Leaf(leaf) => occupied(leaf),
+            NStack::Node(node) => occupied(node),
+        }
+    }
+}
+
+fn occupied<E>(slots: &[Option<E>; N]) -> usize {
+    slots.iter().take_while(|slot| slot.is_some()).count()
+}
+
+/// Returns the child holding `index` and the index of its first element.
+///
+/// If `index` falls between two children, the left one is picked when
+/// `append` is set, since inserting at its end keeps the order.
+fn locate<T, A>(node: &Children<T, A>, index: u64, append: bool) -> (usize, u64)
+where
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    let mut offset = 0;
+    let mut last = 0;
+
+    for (i, annotated) in node.iter().enumerate() {
+        let annotated = match annotated {
+            Some(annotated) => annotated,
+            None => break,
+        };
+        let len = **Borrow::<Cardinality>::borrow(&*annotated.anno());
+
+        if index < offset + len || (append && index == offset + len) {
+            return (i, offset);
+        }
+
+        last = i;
+        offset += len;
+    }
+
+    (last, offset)
+}
+
+/// Insert `e` at `i`, shifting the slots after it. If the slots are full,
+/// they are split in two, returning the right half.
+fn insert_slot<E>(
+    slots: &mut [Option<E>; N],
+    i: usize,
+    e: E,
+) -> Option<[Option<E>; N]> {
+    if occupied(slots) < N {
+        slots[i..].rotate_right(1);
+        slots[i] = Some(e);
+        return None;
+    }
+
+    let mut right = [None, None, None, None];
+    for (k, slot) in slots[MIN..].iter_mut().enumerate() {
+        right[k] = slot.take();
+    }
+
+    if i <= MIN {
+        insert_slot(slots, i, e);
+    } else {
+        insert_slot(&mut right, i - MIN, e);
+    }
+
+    Some(right)
+}
+
+/// Rebalance the under-full child `i` of a node with one of its siblings,
+/// removing the sibling if they are merged
+fn rebalance<T, A>(node: &mut Children<T, A>, i: usize)
+where
+    A: Annotation<NStack<T, A>>,
+{
+    let count = occupied(node);
+    if count < 2 {
+        return;
+    }
+
+    let (l, r) = if i + 1 < count {
+        (i, i + 1)
+    } else {
+        (i - 1, i)
+    };
+
+    let (head, tail) = node.split_at_mut(r);
+    let mut left = head[l].as_mut().expect("child exists").child_mut();
+    let mut right = tail[0].as_mut().expect("child exists").child_mut();
+
+    let merged = match (&mut **left, &mut **right) {
+        (NStack::Leaf(left), NStack::Leaf(right)) => balance(left, right),
+        (NStack::Node(left), NStack::Node(right)) => balance(left, right),
+        _ => unreachable!(),
+    };
+
+    if merged {
+        node[r] = None;
+        node[r..].rotate_left(1);
+    }
+}
+
+/// Move all slots of `right` into `left` if they fit, returning true, or
+/// else move slots over until both hold at least [`MIN`]
+fn balance<E>(left: &mut [Option<E>; N], right: &mut [Option<E>; N]) -> bool {
+    let (a, b) = (occupied(left), occupied(right));
+
+    if a + b <= N {
+        for k in 0..b {
+            left[a + k] = right[k].take();
+        }
+        return true;
+    }
+
+    let (mut a, mut b) = (a, b);
+    while a < MIN {
+        left[a] = right[0].take();
+        right.rotate_left(1);
+        a += 1;
+    }
+    while b < MIN {
+        right.rotate_right(1);
+        right[0] = left[a - 1].take();
+        a -= 1;
+        b += 1;
+    }
+
+    false
+}
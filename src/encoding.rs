@@ -0,0 +1,316 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Canonical binary encoding of stacks.
+//!
+//! The encoding depends only on the sequence of elements in a stack, and not
+//! on its shape in memory, so equal stacks always encode to the same bytes.
+//! Version 1 of the format is laid out as follows, with all integers in
+//! little endian:
+//!
+//! | Size     | Content                                              |
+//! |----------|------------------------------------------------------|
+//! | 1        | format version, `1`                                  |
+//! | 1        | height of a stack holding the elements, at least `1` |
+//! | 8        | number of elements                                   |
+//! | 4 + len  | for each element, its length and its [`Codec`] bytes |
+//!
+//! The height is the smallest one able to hold all elements, the height a
+//! stack built by pushing them would have.
+
+use crate::annotation::{Bloom, Cardinality, MaxKey};
+use crate::{NStack, N};
+
+use alloc::vec::Vec;
+
+use core::fmt;
+
+use ranno::Annotation;
+
+/// The current version of the encoding
+pub const VERSION: u8 = 1;
+
+/// Encoding of the elements of a stack.
+///
+/// For the encoding of a stack to be canonical, every value must have
+/// exactly one encoding, that is `decode` must only accept the bytes
+/// produced by `encode`. Decoding a stack rejects any element whose bytes
+/// don't encode back to the same bytes.
+pub trait Codec: Sized {
+    /// Append the encoding of the value to `buf`
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decode a value from its bytes, returning `None` if they are invalid
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! int_codec {
+    ($($int:ty),*) => {
+        $(
+            impl Codec for $int {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    Some(Self::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Codec for () {
+    fn encode(&self, _: &mut Vec<u8>) {}
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes.is_empty().then_some(())
+    }
+}
+
+impl Codec for Cardinality {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf)
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        u64::decode(bytes).map(Cardinality::from)
+    }
+}
+
+/// A tag byte, followed by the key if there is one
+impl<K> Codec for MaxKey<K>
+where
+    K: Codec,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            MaxKey::NegativeInfinity => buf.push(0),
+            MaxKey::Maximum(key) => {
+                buf.push(1);
+                key.encode(buf);
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes.split_first()? {
+            (0, []) => Some(MaxKey::NegativeInfinity),
+            (1, key) => K::decode(key).map(MaxKey::Maximum),
+            _ => None,
+        }
+    }
+}
+
+/// The words of the filter, in order
+impl<K> Codec for Bloom<K> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for word in self.words() {
+            word.encode(buf);
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut bloom = Bloom::new();
+        let words = bloom.words_mut();
+
+        if bytes.len() != words.len() * 8 {
+            return None;
+        }
+        for (word, bytes) in words.iter_mut().zip(bytes.chunks_exact(8)) {
+            *word = u64::decode(bytes)?;
+        }
+
+        Some(bloom)
+    }
+}
+
+/// Error decoding a stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The format version is not supported
+    UnsupportedVersion(u8),
+    /// The input ended before the whole stack was read
+    UnexpectedEnd,
+    /// The input continues after the whole stack was read
+    TrailingBytes,
+    /// The height is not the one of a stack holding the elements
+    NonCanonicalHeight { height: u8, expected: u8 },
+    /// The element at the index could not be decoded
+    InvalidElement(u64),
+    /// The element at the index has a different canonical encoding
+    NonCanonicalElement(u64),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported encoding version {}", version)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::TrailingBytes => write!(f, "trailing bytes"),
+            DecodeError::NonCanonicalHeight { height, expected } => write!(
+                f,
+                "height is {}, but the canonical height is {}",
+                height, expected
+            ),
+            DecodeError::InvalidElement(index) => {
+                write!(f, "invalid element at index {}", index)
+            }
+            DecodeError::NonCanonicalElement(index) => {
+                write!(f, "non-canonical element at index {}", index)
+            }
+        }
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    T: Codec,
+{
+    /// Encode the stack in its canonical binary encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoding of an element is longer than `u32::MAX` bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut elements = Vec::new();
+        let len = encode_elements(self, &mut elements);
+
+        let mut buf = Vec::with_capacity(10 + elements.len());
+        buf.push(VERSION);
+        buf.push(canonical_height(len));
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&elements);
+
+        buf
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    T: Codec,
+    A: Annotation<Self>,
+{
+    /// Decode a stack from its canonical binary encoding.
+    ///
+    /// Only the exact bytes produced by [`NStack::encode`] are accepted.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader(bytes);
+
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let height = reader.take(1)?[0];
+        let len = u64::from_le_bytes(reader.array()?);
+
+        let expected = canonical_height(len);
+        if height != expected {
+            return Err(DecodeError::NonCanonicalHeight { height, expected });
+        }
+
+        let mut stack = NStack::new();
+        let mut buf = Vec::new();
+
+        for index in 0..len {
+            let size = u32::from_le_bytes(reader.array()?);
+            let bytes = reader.take(size as usize)?;
+
+            let t =
+                T::decode(bytes).ok_or(DecodeError::InvalidElement(index))?;
+
+            buf.clear();
+            t.encode(&mut buf);
+            if buf != bytes {
+                return Err(DecodeError::NonCanonicalElement(index));
+            }
+
+            stack.push(t);
+        }
+
+        if !reader.0.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        Ok(stack)
+    }
+}
+
+/// Append the length prefixed encodings of the elements of the stack to
+/// `buf`, returning their number
+fn encode_elements<T, A>(stack: &NStack<T, A>, buf: &mut Vec<u8>) -> u64
+where
+    T: Codec,
+{
+    let mut len = 0;
+    match stack {
+        NStack::Leaf(leaf) => {
+            for t in leaf.iter().flatten() {
+                write_entry(buf, t);
+                len += 1;
+            }
+        }
+        NStack::Node(node) => {
+            for annotated in node.iter().flatten() {
+                len += encode_elements(annotated.child(), buf);
+            }
+        }
+    }
+    len
+}
+
+/// Append the length prefixed encoding of the value to `buf`.
+///
+/// # Panics
+///
+/// Panics if the encoding of the value is longer than `u32::MAX` bytes.
+pub(crate) fn write_entry<V: Codec>(buf: &mut Vec<u8>, value: &V) {
+    let at = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    value.encode(buf);
+
+    let size = buf.len() - at - 4;
+    let size = u32::try_from(size).unwrap_or_else(|_| {
+        panic!("encoded value (is {size} bytes) should fit in u32::MAX bytes")
+    });
+    buf[at..at + 4].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Returns the height of a stack of `len` elements built by pushing
+pub(crate) fn canonical_height(len: u64) -> u8 {
+    let mut height = 1;
+    let mut capacity = N as u64;
+    while capacity < len {
+        height += 1;
+        capacity = capacity.saturating_mul(N as u64);
+    }
+    height
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < n {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn array<const S: usize>(
+        &mut self,
+    ) -> Result<[u8; S], DecodeError> {
+        let bytes = self.take(S)?;
+        Ok(bytes.try_into().expect("taken the array size"))
+    }
+}
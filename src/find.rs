@@ -0,0 +1,361 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Searches over an [`NStack`], pruned by its annotations.
+
+use crate::annotation::Cardinality;
+use crate::NStack;
+
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+
+use microkelvin::{Branch, BranchMut, Child, Compound, Step, Walk, Walker};
+use ranno::Annotation;
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self>,
+{
+    /// Construct a [`Branch`] pointing to the first element matching `leaf`,
+    /// only descending into subtrees whose annotation matches `anno`
+    pub fn find<FA, FL>(
+        &self,
+        anno: FA,
+        leaf: FL,
+    ) -> Option<Branch<'_, Self, A>>
+    where
+        FA: FnMut(&A) -> bool,
+        FL: FnMut(&T) -> bool,
+    {
+        Branch::walk(self, Find::new(anno, leaf))
+    }
+
+    /// Construct a [`BranchMut`] pointing to the first element matching
+    /// `leaf`, only descending into subtrees whose annotation matches `anno`
+    pub fn find_mut<FA, FL>(
+        &mut self,
+        anno: FA,
+        leaf: FL,
+    ) -> Option<BranchMut<'_, Self, A>>
+    where
+        FA: FnMut(&A) -> bool,
+        FL: FnMut(&T) -> bool,
+    {
+        BranchMut::walk(self, Find::new(anno, leaf))
+    }
+
+    /// Iterate over all the elements matching `leaf`, only descending into
+    /// subtrees whose annotation matches `anno`
+    pub fn find_all<FA, FL>(
+        &self,
+        anno: FA,
+        leaf: FL,
+    ) -> FindAll<'_, T, A, Find<FA, FL>>
+    where
+        FA: FnMut(&A) -> bool,
+        FL: FnMut(&T) -> bool,
+    {
+        FindAll {
+            root: self,
+            walker: Pruned::new(Find::new(anno, leaf)),
+            done: false,
+        }
+    }
+
+    /// Iterate over all the elements matching the given [`Filter`], together
+    /// with their index, pruning the subtrees the filter rules out
+    pub fn filter_walk<F>(&self, filter: F) -> FilterWalk<'_, T, A, F>
+    where
+        A: Borrow<Cardinality>,
+        F: Filter<T, A>,
+    {
+        FilterWalk {
+            root: self,
+            walker: Pruned::new(filter),
+            done: false,
+        }
+    }
+}
+
+/// A predicate over the annotations and the leaves of an [`NStack`].
+pub trait Filter<T, A> {
+    /// Returns `true` if a subtree with the given annotation may contain a
+    /// matching leaf
+    fn anno(&mut self, anno: &A) -> bool;
+
+    /// Returns `true` if the leaf matches
+    fn leaf(&mut self, leaf: &T) -> bool;
+}
+
+/// A [`Walker`] finding the first leaf matching a closure, pruning the
+/// subtrees whose annotation does not match another.
+///
+/// The annotation closure should return `true` if a subtree with the given
+/// annotation may contain a matching leaf.
+pub struct Find<FA, FL> {
+    anno: FA,
+    leaf: FL,
+}
+
+impl<FA, FL> Find<FA, FL> {
+    /// Create a new walker from an annotation and a leaf closure
+    pub fn new(anno: FA, leaf: FL) -> Self {
+        Self { anno, leaf }
+    }
+}
+
+impl<T, A, FA, FL> Filter<T, A> for Find<FA, FL>
+where
+    FA: FnMut(&A) -> bool,
+    FL: FnMut(&T) -> bool,
+{
+    fn anno(&mut self, anno: &A) -> bool {
+        (self.anno)(anno)
+    }
+
+    fn leaf(&mut self, leaf: &T) -> bool {
+        (self.leaf)(leaf)
+    }
+}
+
+impl<T, A, FA, FL> Walker<NStack<T, A>, A> for Find<FA, FL>
+where
+    A: Annotation<NStack<T, A>>,
+    FA: FnMut(&A) -> bool,
+    FL: FnMut(&T) -> bool,
+{
+    fn walk(&mut self, walk: Walk<NStack<T, A>, A>) -> Step {
+        for i in 0.. {
+            match walk.child(i) {
+                Child::Leaf(l) => {
+                    if self.leaf(l) {
+                        return Step::Found(i);
+                    }
+                }
+                Child::Node(n) => {
+                    if self.anno(&n.anno()) {
+                        return Step::Into(i);
+                    }
+                }
+                Child::Empty => (),
+                Child::EndOfNode => return Step::Advance,
+            }
+        }
+        unreachable!()
+    }
+}
+
+/// A [`Walker`] finding the first leaf matching a [`Filter`], descending
+/// only into the subtrees whose annotation the filter matches and advancing
+/// past the rest.
+pub struct Pruned<F> {
+    filter: F,
+    // child indices leading to the last leaf found, to resume the walk after
+    resume: Vec<usize>,
+    depth: usize,
+}
+
+impl<F> Pruned<F> {
+    /// Create a new walker from a filter
+    pub fn new(filter: F) -> Self {
+        Self {
+            filter,
+            resume: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    /// Resume the next walk after the leaf at the end of `path`
+    fn resume_after(&mut self, path: Vec<usize>) {
+        self.resume = path;
+        self.depth = 0;
+    }
+
+    fn step<C, A>(&mut self, walk: Walk<C, A>) -> Step
+    where
+        C: Compound<A>,
+        A: Annotation<C>,
+        F: Filter<C::Leaf, A>,
+    {
+        let mut start = 0;
+
+        // follow the path to the last leaf found, down to its level
+        if let Some(&i) = self.resume.get(self.depth) {
+            if self.depth + 1 < self.resume.len() {
+                self.depth += 1;
+                return Step::Into(i);
+            }
+            self.resume.clear();
+            start = i + 1;
+        }
+
+        for i in start.. {
+            match walk.child(i) {
+                Child::Leaf(l) => {
+                    if self.filter.leaf(l) {
+                        return Step::Found(i);
+                    }
+                }
+                Child::Node(n) => {
+                    if self.filter.anno(&n.anno()) {
+                        self.depth += 1;
+                        return Step::Into(i);
+                    }
+                }
+                Child::Empty => (),
+                Child::EndOfNode => {
+                    self.depth = self.depth.saturating_sub(1);
+                    return Step::Advance;
+                }
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl<C, A, F> Walker<C, A> for Pruned<F>
+where
+    C: Compound<A>,
+    A: Annotation<C>,
+    F: Filter<C::Leaf, A>,
+{
+    fn walk(&mut self, walk: Walk<C, A>) -> Step {
+        self.step(walk)
+    }
+}
+
+impl<C, A, F> Walker<C, A> for &mut Pruned<F>
+where
+    C: Compound<A>,
+    A: Annotation<C>,
+    F: Filter<C::Leaf, A>,
+{
+    fn walk(&mut self, walk: Walk<C, A>) -> Step {
+        self.step(walk)
+    }
+}
+
+/// Walk to the next leaf matching the walker's filter, resuming after the
+/// last one found, and returning the path of child indices leading to it
+fn walk_next<T, A, F>(
+    root: &NStack<T, A>,
+    walker: &mut Pruned<F>,
+) -> Option<Vec<usize>>
+where
+    A: Annotation<NStack<T, A>>,
+    F: Filter<T, A>,
+{
+    let branch = Branch::walk(root, &mut *walker)?;
+    let path: Vec<_> = branch.levels().iter().map(|l| l.index()).collect();
+
+    walker.resume_after(path.clone());
+    Some(path)
+}
+
+/// Returns the leaf at the end of a path of child indices
+fn leaf_at<'a, T, A>(root: &'a NStack<T, A>, path: &[usize]) -> &'a T {
+    let mut block = root;
+    for &i in path {
+        match block.child(i) {
+            Child::Leaf(leaf) => return leaf,
+            Child::Node(annotated) => block = annotated.child(),
+            _ => break,
+        }
+    }
+    unreachable!("the path leads to a leaf")
+}
+
+/// Returns the index of the leaf at the end of a path of child indices
+fn index_at<T, A>(root: &NStack<T, A>, path: &[usize]) -> u64
+where
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    let mut index = 0;
+    let mut block = root;
+
+    for &i in path {
+        for j in 0..i {
+            index += match block.child(j) {
+                Child::Leaf(_) => 1,
+                Child::Node(annotated) => {
+                    **Borrow::<Cardinality>::borrow(&*annotated.anno())
+                }
+                _ => 0,
+            };
+        }
+        if let Child::Node(annotated) = block.child(i) {
+            block = annotated.child();
+        }
+    }
+
+    index
+}
+
+/// Iterator over all the elements matching a [`Filter`].
+///
+/// Created by [`NStack::find_all`].
+pub struct FindAll<'a, T, A, F> {
+    root: &'a NStack<T, A>,
+    walker: Pruned<F>,
+    done: bool,
+}
+
+impl<'a, T, A, F> Iterator for FindAll<'a, T, A, F>
+where
+    A: Annotation<NStack<T, A>>,
+    F: Filter<T, A>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match walk_next(self.root, &mut self.walker) {
+            Some(path) => Some(leaf_at(self.root, &path)),
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// Iterator over all the elements matching a [`Filter`], together with their
+/// index.
+///
+/// Created by [`NStack::filter_walk`].
+pub struct FilterWalk<'a, T, A, F> {
+    root: &'a NStack<T, A>,
+    walker: Pruned<F>,
+    done: bool,
+}
+
+impl<'a, T, A, F> Iterator for FilterWalk<'a, T, A, F>
+where
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+    F: Filter<T, A>,
+{
+    type Item = (u64, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match walk_next(self.root, &mut self.walker) {
+            Some(path) => {
+                Some((index_at(self.root, &path), leaf_at(self.root, &path)))
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
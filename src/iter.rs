@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Iteration over the elements of a stack.
+
+use crate::persistent::PersistentNStack;
+use crate::NStack;
+
+use alloc::vec::Vec;
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use microkelvin::{Child, Compound};
+use ranno::Annotation;
+
+impl<T, A> NStack<T, A> {
+    /// Iterate over the elements of the stack, from the bottom up
+    pub fn iter(&self) -> Iter<'_, Self, A> {
+        Iter::new(self)
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self>,
+{
+    /// Call `f` on every element of the stack, from the bottom up.
+    ///
+    /// Every annotation is recomputed exactly once, after all the elements
+    /// below it are visited.
+    pub fn for_each_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut T),
+    {
+        let _ = self.try_visit::<Infallible, _>(&mut |t| {
+            f(t);
+            Ok(())
+        });
+    }
+
+    /// Call `f` on every element of the stack, from the bottom up, stopping
+    /// at the first error.
+    ///
+    /// Every annotation above a visited element is recomputed exactly once,
+    /// including when an error is returned.
+    pub fn try_for_each_mut<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut T) -> Result<(), E>,
+    {
+        self.try_visit(&mut f)
+    }
+
+    fn try_visit<E, F>(&mut self, f: &mut F) -> Result<(), E>
+    where
+        F: FnMut(&mut T) -> Result<(), E>,
+    {
+        match self {
+            NStack::Leaf(leaf) => leaf.iter_mut().flatten().try_for_each(f),
+            NStack::Node(node) => {
+                for annotated in node.iter_mut().flatten() {
+                    let result = annotated.child_mut().try_visit(f);
+                    drop(annotated.anno());
+                    result?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T, A> PersistentNStack<T, A>
+where
+    T: Clone,
+    A: Annotation<Self>,
+{
+    /// Iterate over the elements of the stack, from the bottom up
+    pub fn iter(&self) -> Iter<'_, Self, A> {
+        Iter::new(self)
+    }
+}
+
+/// Iterator over the elements of a stack.
+///
+/// Created by [`NStack::iter`], [`PersistentNStack::iter`] and
+/// `StoredNStack::iter`.
+pub struct Iter<'a, C, A> {
+    // blocks from the root to the current leaf, and the next child index
+    path: Vec<(&'a C, usize)>,
+    _marker: PhantomData<A>,
+}
+
+impl<'a, C, A> Iter<'a, C, A> {
+    pub(crate) fn new(root: &'a C) -> Self {
+        Self {
+            path: alloc::vec![(root, 0)],
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, C, A> Iterator for Iter<'a, C, A>
+where
+    C: Compound<A>,
+    A: 'a,
+{
+    type Item = &'a C::Leaf;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (block, i) = self.path.last_mut()?;
+            let block: &'a C = block;
+
+            let index = *i;
+            *i += 1;
+
+            match block.child(index) {
+                Child::Leaf(t) => return Some(t),
+                Child::Node(annotated) => {
+                    self.path.push((annotated.child(), 0))
+                }
+                Child::Empty => (),
+                Child::EndOfNode => {
+                    self.path.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T, A> IntoIterator for &'a NStack<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, NStack<T, A>, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,309 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Write-ahead journal of the edits made to a stack.
+//!
+//! A [`Journal`] is an append-only log of edits, encoded with the element
+//! [`Codec`]. Replaying it onto a snapshot of a stack, taken when the journal
+//! was started, reconstructs the stack. Every record is laid out as follows,
+//! with all integers in little endian:
+//!
+//! | Edit       | Record                                                 |
+//! |------------|--------------------------------------------------------|
+//! | `push`     | `0`, the element length as 4 bytes, the element        |
+//! | `pop`      | `1`                                                    |
+//! | `truncate` | `2`, the length as 8 bytes                             |
+//! | `nth_mut`  | `3`, the index as 8 bytes, the element length as 4     |
+//! |            | bytes, and the element as it was when the edit ended   |
+
+use crate::annotation::Cardinality;
+use crate::encoding::{write_entry, Codec, DecodeError, Reader};
+use crate::NStack;
+
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use microkelvin::BranchMut;
+use ranno::Annotation;
+
+const PUSH: u8 = 0;
+const POP: u8 = 1;
+const TRUNCATE: u8 = 2;
+const WRITE: u8 = 3;
+
+/// An append-only log of the edits made to a stack.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Journal {
+    bytes: Vec<u8>,
+}
+
+impl Journal {
+    /// Creates a new empty Journal
+    pub const fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    /// Creates a Journal from previously recorded bytes
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the recorded bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns true if no edit was recorded
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Remove all recorded edits, for instance after taking a snapshot
+    pub fn clear(&mut self) {
+        self.bytes.clear()
+    }
+}
+
+/// Error replaying a journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    /// The journal ended in the middle of the record at the index
+    UnexpectedEnd(u64),
+    /// The record at the index is invalid or can't be applied
+    InvalidRecord(u64),
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::UnexpectedEnd(index) => {
+                write!(f, "unexpected end of journal record at index {}", index)
+            }
+            JournalError::InvalidRecord(index) => {
+                write!(f, "invalid journal record at index {}", index)
+            }
+        }
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    T: Codec,
+    A: Annotation<Self> + Borrow<Cardinality>,
+{
+    /// Reconstruct a stack by replaying all edits of a journal started on
+    /// an empty stack
+    pub fn replay(journal: &Journal) -> Result<Self, JournalError> {
+        let mut stack = Self::new();
+        stack.apply(journal)?;
+        Ok(stack)
+    }
+
+    /// Replay all edits of a journal, started on a snapshot of this stack.
+    ///
+    /// If the journal is malformed, the edits before the malformed record
+    /// are kept applied.
+    pub fn apply(&mut self, journal: &Journal) -> Result<(), JournalError> {
+        let mut reader = Reader(&journal.bytes);
+        let mut record = 0;
+
+        while !reader.0.is_empty() {
+            // the reader only fails when running out of bytes
+            let end = |_: DecodeError| JournalError::UnexpectedEnd(record);
+
+            match reader.take(1).map_err(end)?[0] {
+                PUSH => {
+                    let t = read_element(&mut reader, record)?;
+                    self.push(t);
+                }
+                POP => {
+                    self.pop().ok_or(JournalError::InvalidRecord(record))?;
+                }
+                TRUNCATE => {
+                    let len = u64::from_le_bytes(reader.array().map_err(end)?);
+                    self.truncate(len);
+                }
+                WRITE => {
+                    let index =
+                        u64::from_le_bytes(reader.array().map_err(end)?);
+                    let t = read_element(&mut reader, record)?;
+                    let mut branch = self
+                        .nth_mut(index)
+                        .ok_or(JournalError::InvalidRecord(record))?;
+                    *branch = t;
+                }
+                _ => return Err(JournalError::InvalidRecord(record)),
+            }
+            record += 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_element<T: Codec>(
+    reader: &mut Reader,
+    record: u64,
+) -> Result<T, JournalError> {
+    let end = |_: DecodeError| JournalError::UnexpectedEnd(record);
+
+    let size = u32::from_le_bytes(reader.array().map_err(end)?);
+    let bytes = reader.take(size as usize).map_err(end)?;
+    T::decode(bytes).ok_or(JournalError::InvalidRecord(record))
+}
+
+/// An [`NStack`] recording every edit made to it in a [`Journal`].
+#[derive(Debug)]
+pub struct Journaled<T, A> {
+    stack: NStack<T, A>,
+    journal: Journal,
+}
+
+impl<T, A> Journaled<T, A> {
+    /// Creates a new empty Journaled stack
+    pub const fn new() -> Self {
+        Self {
+            stack: NStack::new(),
+            journal: Journal::new(),
+        }
+    }
+
+    /// Returns the journal of the edits made since it was started
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Returns the journal, starting a new one on the current stack
+    pub fn take_journal(&mut self) -> Journal {
+        core::mem::take(&mut self.journal)
+    }
+
+    /// Consume the structure, returning the inner stack and its journal
+    pub fn into_inner(self) -> (NStack<T, A>, Journal) {
+        (self.stack, self.journal)
+    }
+}
+
+impl<T, A> Journaled<T, A>
+where
+    T: Codec,
+    A: Annotation<NStack<T, A>>,
+{
+    /// Pushes a new element onto the stack
+    pub fn push(&mut self, t: T) {
+        self.journal.bytes.push(PUSH);
+        write_entry(&mut self.journal.bytes, &t);
+        self.stack.push(t);
+    }
+
+    /// Pop an element off the stack.
+    ///
+    /// Returns the popped element, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let t = self.stack.pop()?;
+        self.journal.bytes.push(POP);
+        Some(t)
+    }
+}
+
+impl<T, A> Journaled<T, A>
+where
+    T: Codec,
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    /// Shortens the stack, keeping the first `len` elements and dropping the
+    /// rest
+    pub fn truncate(&mut self, len: u64) {
+        self.journal.bytes.push(TRUNCATE);
+        self.journal.bytes.extend_from_slice(&len.to_le_bytes());
+        self.stack.truncate(len);
+    }
+
+    /// Construct a [`JournaledMut`] pointing to the `nth` element, if any.
+    ///
+    /// The edit is recorded when the returned value is dropped.
+    pub fn nth_mut(&mut self, index: u64) -> Option<JournaledMut<'_, T, A>> {
+        let branch = self.stack.nth_mut(index)?;
+        Some(JournaledMut {
+            branch,
+            index,
+            journal: &mut self.journal,
+        })
+    }
+}
+
+/// A mutable reference to an element of a [`Journaled`] stack, recording
+/// the element in the journal when dropped.
+pub struct JournaledMut<'a, T, A>
+where
+    T: Codec,
+    A: Annotation<NStack<T, A>>,
+{
+    branch: BranchMut<'a, NStack<T, A>, A>,
+    index: u64,
+    journal: &'a mut Journal,
+}
+
+impl<'a, T, A> Deref for JournaledMut<'a, T, A>
+where
+    T: Codec,
+    A: Annotation<NStack<T, A>>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.branch
+    }
+}
+
+impl<'a, T, A> DerefMut for JournaledMut<'a, T, A>
+where
+    T: Codec,
+    A: Annotation<NStack<T, A>>,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.branch
+    }
+}
+
+impl<'a, T, A> Drop for JournaledMut<'a, T, A>
+where
+    T: Codec,
+    A: Annotation<NStack<T, A>>,
+{
+    fn drop(&mut self) {
+        let bytes = &mut self.journal.bytes;
+        bytes.push(WRITE);
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        write_entry(bytes, &*self.branch);
+    }
+}
+
+impl<T, A> Deref for Journaled<T, A> {
+    type Target = NStack<T, A>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stack
+    }
+}
+
+impl<T, A> Default for Journaled<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A> From<NStack<T, A>> for Journaled<T, A> {
+    fn from(stack: NStack<T, A>) -> Self {
+        Self {
+            stack,
+            journal: Journal::new(),
+        }
+    }
+}
@@ -10,12 +10,43 @@
 #![no_std]
 
 pub mod annotation;
+#[cfg(feature = "rkyv")]
+pub mod archive;
+pub mod block;
+pub mod checkpoint;
+mod cmp;
+pub mod cursor;
+pub mod diff;
+mod edit;
+pub mod encoding;
+pub mod find;
+pub mod iter;
+pub mod journal;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod persistent;
+pub mod render;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod stats;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub mod testing;
+pub mod tombstone;
+pub mod validate;
 
 extern crate alloc;
+#[cfg(feature = "store")]
+extern crate std;
 use alloc::boxed::Box;
 
+use core::borrow::Borrow;
 use core::mem;
 
+use crate::annotation::Cardinality;
+use crate::block::{Block, Blocks};
+
 use microkelvin::{Child, ChildMut, Compound, MutableLeaves};
 use ranno::{Annotated, Annotation};
 
@@ -34,6 +65,17 @@ impl<T, A> NStack<T, A> {
     pub const fn new() -> Self {
         NStack::Leaf([None, None, None, None])
     }
+
+    /// Returns the number of levels in the tree
+    pub(crate) fn height(&self) -> usize {
+        match self {
+            NStack::Leaf(_) => 1,
+            NStack::Node(node) => match &node[0] {
+                Some(annotated) => 1 + annotated.child().height(),
+                None => 1,
+            },
+        }
+    }
 }
 
 enum Push<T> {
@@ -50,7 +92,7 @@ enum Pop<T> {
 impl<T, A> Compound<A> for NStack<T, A> {
     type Leaf = T;
 
-    fn child(&self, index: usize) -> Child<Self, A> {
+    fn child(&self, index: usize) -> Child<'_, Self, A> {
         match (index, self) {
             (0, NStack::Node([Some(a), _, _, _])) => Child::Node(a),
             (1, NStack::Node([_, Some(b), _, _])) => Child::Node(b),
@@ -64,7 +106,7 @@ impl<T, A> Compound<A> for NStack<T, A> {
         }
     }
 
-    fn child_mut(&mut self, index: usize) -> ChildMut<Self, A> {
+    fn child_mut(&mut self, index: usize) -> ChildMut<'_, Self, A> {
         match (index, self) {
             (0, NStack::Node([Some(a), _, _, _])) => ChildMut::Node(a),
             (1, NStack::Node([_, Some(b), _, _])) => ChildMut::Node(b),
@@ -104,13 +146,10 @@ where
     fn _push(&mut self, t: T) -> Push<T> {
         match self {
             NStack::Leaf(leaf) => {
-                for mut item in leaf.iter_mut() {
-                    match item {
-                        ref mut empty @ None => {
-                            **empty = Some(t);
-                            return Push::Ok;
-                        }
-                        Some(_) => (),
+                for item in leaf.iter_mut() {
+                    if item.is_none() {
+                        *item = Some(t);
+                        return Push::Ok;
                     }
                 }
                 Push::NoRoom { t, depth: 0 }
@@ -145,10 +184,8 @@ where
 
                                         // give it enough depth
                                         for _ in 0..depth {
-                                            let old_root = mem::replace(
-                                                &mut new_node,
-                                                NStack::new(),
-                                            );
+                                            let old_root =
+                                                mem::take(&mut new_node);
                                             new_node = NStack::Node([
                                                 Some(Annotated::new(Box::new(
                                                     old_root,
@@ -238,6 +275,32 @@ where
     }
 }
 
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self> + Borrow<Cardinality>,
+{
+    /// Shortens the stack, keeping the first `len` elements and dropping the
+    /// rest.
+    ///
+    /// Has no effect if `len` is greater than the current length.
+    pub fn truncate(&mut self, len: u64) {
+        let mut current = **A::from_child(self).borrow();
+        while current > len {
+            self.pop();
+            current -= 1;
+        }
+    }
+}
+
+impl<T, A> Blocks<T, A> for NStack<T, A> {
+    fn block(&self) -> Block<'_, T, A, Self> {
+        match self {
+            NStack::Leaf(leaf) => Block::Leaf(leaf),
+            NStack::Node(node) => Block::Node(node),
+        }
+    }
+}
+
 impl<T, A> MutableLeaves for NStack<T, A> {}
 
 impl<T, A> Default for NStack<T, A> {
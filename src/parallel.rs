@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Parallel construction, iteration and mutation of stacks, using `rayon`.
+
+use crate::{NStack, NStackRef, N};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use ranno::{Annotated, Annotation};
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::prelude::*;
+
+impl<T, A> NStack<T, A>
+where
+    T: Send,
+    A: Annotation<Self> + Send,
+{
+    /// Build a stack holding the elements of `elements`, in order.
+    ///
+    /// The result has the same shape as a stack built by pushing the
+    /// elements one by one, but it is built level by level from the leaves
+    /// up, with the blocks of each level built, and their annotations
+    /// computed, concurrently.
+    pub fn par_from_vec(elements: Vec<T>) -> Self {
+        let mut blocks: Vec<Self> = elements
+            .into_par_iter()
+            .chunks(N)
+            .map(|chunk| {
+                let mut leaf = [None, None, None, None];
+                for (slot, t) in leaf.iter_mut().zip(chunk) {
+                    *slot = Some(t);
+                }
+                NStack::Leaf(leaf)
+            })
+            .collect();
+
+        // every block but the last of a level is full
+        while blocks.len() > 1 {
+            blocks = blocks
+                .into_par_iter()
+                .chunks(N)
+                .map(|chunk| {
+                    let mut node = [None, None, None, None];
+                    for (slot, child) in node.iter_mut().zip(chunk) {
+                        let annotated = Annotated::new(Box::new(child));
+                        // compute the annotation while still on the worker
+                        drop(annotated.anno());
+                        *slot = Some(annotated);
+                    }
+                    NStack::Node(node)
+                })
+                .collect();
+        }
+
+        blocks.pop().unwrap_or_default()
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    T: Send,
+    A: Annotation<Self> + Send,
+{
+    /// Call `f` on every element of the stack, in parallel.
+    ///
+    /// The children of every `Node` are visited concurrently, and each
+    /// annotation is recomputed once, by the worker that visited its
+    /// subtree, after all its descendants are done.
+    pub fn par_for_each_mut<F>(&mut self, f: F)
+    where
+        F: Fn(&mut T) + Sync,
+    {
+        par_visit(self, &f)
+    }
+}
+
+fn par_visit<T, A, F>(stack: &mut NStack<T, A>, f: &F)
+where
+    T: Send,
+    A: Annotation<NStack<T, A>> + Send,
+    F: Fn(&mut T) + Sync,
+{
+    match stack {
+        NStack::Leaf(leaf) => leaf.iter_mut().flatten().for_each(f),
+        NStack::Node(node) => {
+            node.par_iter_mut().flatten().for_each(|annotated| {
+                par_visit(&mut annotated.child_mut(), f);
+                drop(annotated.anno());
+            })
+        }
+    }
+}
+
+/// A block of a stack, shared between threads.
+///
+/// Annotations are cached in a `RefCell`, so a stack isn't `Sync`. Walking
+/// its blocks never touches the cache though, so a block is only shared to
+/// do that.
+struct Shared<'a, T, A>(&'a NStack<T, A>);
+
+// SAFETY: only the children and the elements of the block are ever read
+// through a `Shared`, never the annotation caches, and the elements are
+// `Sync`
+unsafe impl<T, A> Send for Shared<'_, T, A> where T: Sync {}
+
+/// Parallel iterator over the elements of a stack.
+///
+/// Work is split along the children of every `Node`, as they are reached.
+pub struct ParIter<'a, T, A> {
+    block: Shared<'a, T, A>,
+}
+
+impl<'a, T, A> ParallelIterator for ParIter<'a, T, A>
+where
+    T: Sync,
+{
+    type Item = &'a T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        match self.block.0 {
+            NStack::Leaf(leaf) => leaf
+                .par_iter()
+                .filter_map(Option::as_ref)
+                .drive_unindexed(consumer),
+            NStack::Node(node) => {
+                let children: [_; N] = core::array::from_fn(|i| {
+                    node[i]
+                        .as_ref()
+                        .map(|annotated| Shared(&**annotated.child()))
+                });
+                children
+                    .into_par_iter()
+                    .flatten()
+                    .flat_map(|block| ParIter { block })
+                    .drive_unindexed(consumer)
+            }
+        }
+    }
+}
+
+impl<'a, T, A> IntoParallelIterator for &'a NStack<T, A>
+where
+    T: Sync,
+{
+    type Item = &'a T;
+    type Iter = ParIter<'a, T, A>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter {
+            block: Shared(self),
+        }
+    }
+}
+
+/// Parallel iterator over mutable references to the elements of a stack.
+///
+/// Work is split along the children of every `Node`, as they are reached.
+/// The annotation of every child reached is invalidated, to be recomputed
+/// when next needed.
+pub struct ParIterMut<'a, T, A> {
+    block: &'a mut NStack<T, A>,
+}
+
+impl<'a, T, A> ParallelIterator for ParIterMut<'a, T, A>
+where
+    T: Send,
+    A: Annotation<NStack<T, A>> + Send,
+{
+    type Item = &'a mut T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        match self.block {
+            NStack::Leaf(leaf) => leaf
+                .par_iter_mut()
+                .filter_map(Option::as_mut)
+                .drive_unindexed(consumer),
+            NStack::Node(node) => node
+                .par_iter_mut()
+                .flatten()
+                .flat_map(|annotated| ParIterMut {
+                    block: child_mut(annotated),
+                })
+                .drive_unindexed(consumer),
+        }
+    }
+}
+
+impl<'a, T, A> IntoParallelIterator for &'a mut NStack<T, A>
+where
+    T: Send,
+    A: Annotation<NStack<T, A>> + Send,
+{
+    type Item = &'a mut T;
+    type Iter = ParIterMut<'a, T, A>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIterMut { block: self }
+    }
+}
+
+/// Returns the child of an annotated block for as long as the block is
+/// borrowed, invalidating its annotation
+fn child_mut<T, A>(
+    annotated: &mut Annotated<NStackRef<T, A>, A>,
+) -> &mut NStack<T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    // dereferencing the guard mutably invalidates the annotation
+    let child: *mut NStack<T, A> = &mut **annotated.child_mut();
+    // SAFETY: the guard does nothing more, and the child is borrowed through
+    // `annotated` for as long as it is returned
+    unsafe { &mut *child }
+}
@@ -0,0 +1,256 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Persistent variant of the [`NStack`](crate::NStack).
+
+use crate::block::{Block, Blocks};
+use crate::{Pop, Push, N};
+
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+
+use core::mem;
+
+use microkelvin::{Child, ChildMut, Compound, MutableLeaves};
+use ranno::{Annotated, Annotation};
+
+type PersistentNStackRef<T, A> = Box<PersistentNStack<T, A>>;
+type PersistentNode<T, A> =
+    [Option<Annotated<PersistentNStackRef<T, A>, A>>; N];
+
+/// A stack with the same layout as the [`NStack`](crate::NStack), but with
+/// its blocks shared using reference counting.
+///
+/// Cloning is constant time, and the clones share all their subtrees.
+/// Mutating a stack copies only the blocks on the path to the mutated
+/// element, leaving any other version untouched.
+///
+/// Annotations are cached in a `RefCell`, which makes the stack neither
+/// `Send` nor `Sync`, so its blocks are shared with [`Rc`] rather than
+/// `Arc`.
+#[derive(Debug)]
+pub enum PersistentNStack<T, A> {
+    Leaf(Rc<[Option<T>; N]>),
+    Node(Rc<PersistentNode<T, A>>),
+}
+
+impl<T, A> PersistentNStack<T, A> {
+    /// Creates a new empty PersistentNStack
+    pub fn new() -> Self {
+        PersistentNStack::Leaf(Rc::new([None, None, None, None]))
+    }
+
+    /// Returns true if both stacks share the same root block
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PersistentNStack::Leaf(a), PersistentNStack::Leaf(b)) => {
+                Rc::ptr_eq(a, b)
+            }
+            (PersistentNStack::Node(a), PersistentNStack::Node(b)) => {
+                Rc::ptr_eq(a, b)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T, A> Compound<A> for PersistentNStack<T, A>
+where
+    T: Clone,
+    A: Annotation<Self>,
+{
+    type Leaf = T;
+
+    fn child(&self, index: usize) -> Child<'_, Self, A> {
+        match self {
+            PersistentNStack::Leaf(leaf) => match leaf.get(index) {
+                Some(Some(t)) => Child::Leaf(t),
+                _ => Child::EndOfNode,
+            },
+            PersistentNStack::Node(node) => match node.get(index) {
+                Some(Some(annotated)) => Child::Node(annotated),
+                _ => Child::EndOfNode,
+            },
+        }
+    }
+
+    fn child_mut(&mut self, index: usize) -> ChildMut<'_, Self, A> {
+        match self {
+            PersistentNStack::Leaf(leaf) => {
+                match Rc::make_mut(leaf).get_mut(index) {
+                    Some(Some(t)) => ChildMut::Leaf(t),
+                    _ => ChildMut::EndOfNode,
+                }
+            }
+            PersistentNStack::Node(node) => {
+                match Rc::make_mut(node).get_mut(index) {
+                    Some(Some(annotated)) => ChildMut::Node(annotated),
+                    _ => ChildMut::EndOfNode,
+                }
+            }
+        }
+    }
+}
+
+impl<T, A> PersistentNStack<T, A>
+where
+    T: Clone,
+    A: Annotation<Self>,
+{
+    /// Pushes a new element onto the stack
+    pub fn push(&mut self, t: T) {
+        match self._push(t) {
+            Push::Ok => (),
+            Push::NoRoom { t, .. } => {
+                let old_root = mem::take(self);
+
+                let mut new_node = [None, None, None, None];
+                new_node[0] = Some(Annotated::new(Box::new(old_root)));
+
+                *self = PersistentNStack::Node(Rc::new(new_node));
+
+                // the first child of our new root will be our old root
+                self.push(t)
+            }
+        }
+    }
+
+    fn _push(&mut self, t: T) -> Push<T> {
+        match self {
+            PersistentNStack::Leaf(leaf) => {
+                match leaf.iter().position(Option::is_none) {
+                    Some(i) => {
+                        Rc::make_mut(leaf)[i] = Some(t);
+                        Push::Ok
+                    }
+                    None => Push::NoRoom { t, depth: 0 },
+                }
+            }
+            PersistentNStack::Node(node) => {
+                // the first child of a node is always present
+                let i = node.iter().rposition(Option::is_some).unwrap_or(0);
+                let node = Rc::make_mut(node);
+
+                let pushed = match &mut node[i] {
+                    Some(annotated) => annotated.child_mut()._push(t),
+                    None => Push::NoRoom { t, depth: 0 },
+                };
+
+                match pushed {
+                    Push::Ok => Push::Ok,
+                    // Are we in the last node
+                    Push::NoRoom { t, depth } if i == N - 1 => Push::NoRoom {
+                        t,
+                        depth: depth + 1,
+                    },
+                    Push::NoRoom { t, depth } => {
+                        // create a new node with enough depth
+                        let mut new_node = PersistentNStack::Leaf(Rc::new([
+                            Some(t),
+                            None,
+                            None,
+                            None,
+                        ]));
+
+                        for _ in 0..depth {
+                            let old_root = mem::take(&mut new_node);
+                            new_node = PersistentNStack::Node(Rc::new([
+                                Some(Annotated::new(Box::new(old_root))),
+                                None,
+                                None,
+                                None,
+                            ]));
+                        }
+
+                        node[i + 1] = Some(Annotated::new(Box::new(new_node)));
+                        Push::Ok
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pop an element off the stack.
+    ///
+    /// Returns the popped element, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        match self._pop() {
+            Pop::Ok(t) | Pop::Last(t) => Some(t),
+            Pop::None => None,
+        }
+    }
+
+    fn _pop(&mut self) -> Pop<T> {
+        match self {
+            PersistentNStack::Leaf(leaf) => {
+                let popped = leaf
+                    .iter()
+                    .rposition(Option::is_some)
+                    .and_then(|i| Some((i, Rc::make_mut(leaf)[i].take()?)));
+
+                match popped {
+                    Some((0, t)) => Pop::Last(t),
+                    Some((_, t)) => Pop::Ok(t),
+                    None => Pop::None,
+                }
+            }
+            PersistentNStack::Node(node) => {
+                let i = match node.iter().rposition(Option::is_some) {
+                    Some(i) => i,
+                    None => return Pop::None,
+                };
+                let node = Rc::make_mut(node);
+
+                let popped = match &mut node[i] {
+                    Some(annotated) => annotated.child_mut()._pop(),
+                    None => Pop::None,
+                };
+
+                match popped {
+                    Pop::Last(t) if i > 0 => {
+                        node[i] = None;
+                        Pop::Ok(t)
+                    }
+                    popped => popped,
+                }
+            }
+        }
+    }
+}
+
+impl<T, A> Blocks<T, A> for PersistentNStack<T, A> {
+    fn block(&self) -> Block<'_, T, A, Self> {
+        match self {
+            PersistentNStack::Leaf(leaf) => Block::Leaf(leaf),
+            PersistentNStack::Node(node) => Block::Node(node),
+        }
+    }
+
+    fn shares(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl<T, A> MutableLeaves for PersistentNStack<T, A> {}
+
+impl<T, A> Default for PersistentNStack<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A> Clone for PersistentNStack<T, A> {
+    fn clone(&self) -> Self {
+        match self {
+            PersistentNStack::Leaf(leaf) => {
+                PersistentNStack::Leaf(Rc::clone(leaf))
+            }
+            PersistentNStack::Node(node) => {
+                PersistentNStack::Node(Rc::clone(node))
+            }
+        }
+    }
+}
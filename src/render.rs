@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Rendering the structure of a stack, for debugging.
+
+use crate::NStack;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use core::fmt::{Debug, Write};
+
+use ranno::Annotation;
+
+impl<T, A> NStack<T, A>
+where
+    T: Debug,
+    A: Annotation<Self> + Debug,
+{
+    /// Render the stack as a Graphviz DOT graph.
+    ///
+    /// Every block is drawn as a record showing its annotation above one
+    /// field per slot.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph nstack {\n");
+        out.push_str("    node [shape=record];\n");
+
+        let root = A::from_child(self);
+        let mut next = 0;
+        self.dot_block(&root, &mut next, &mut out);
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render the stack as an indented text tree, with one line per block.
+    ///
+    /// Every block shows its annotation, and leaves also their slots, with
+    /// empty slots drawn as `_`.
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.tree_block(&A::from_child(self), 0, &mut out);
+        out
+    }
+
+    /// Write the block with the given annotation and its descendants,
+    /// returning the identifier of its DOT node
+    fn dot_block(&self, anno: &A, next: &mut usize, out: &mut String) -> usize {
+        let id = *next;
+        *next += 1;
+
+        match self {
+            NStack::Leaf(leaf) => {
+                let slots: String = leaf
+                    .iter()
+                    .map(|slot| match slot {
+                        Some(t) => escape(&format!("{:?}", t)),
+                        None => String::from("_"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("|");
+                let _ = writeln!(
+                    out,
+                    "    b{} [label=\"{{{}|{{{}}}}}\"];",
+                    id,
+                    escape(&format!("{:?}", anno)),
+                    slots
+                );
+            }
+            NStack::Node(node) => {
+                let _ = writeln!(
+                    out,
+                    "    b{} [label=\"{{{}|{{<s0>|<s1>|<s2>|<s3>}}}}\"];",
+                    id,
+                    escape(&format!("{:?}", anno))
+                );
+                for (i, annotated) in node.iter().enumerate() {
+                    if let Some(annotated) = annotated {
+                        let child = annotated.child().dot_block(
+                            &annotated.anno(),
+                            next,
+                            out,
+                        );
+                        let _ =
+                            writeln!(out, "    b{}:s{} -> b{};", id, i, child);
+                    }
+                }
+            }
+        }
+
+        id
+    }
+
+    fn tree_block(&self, anno: &A, depth: usize, out: &mut String) {
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+
+        match self {
+            NStack::Leaf(leaf) => {
+                let _ = write!(out, "Leaf {:?} [", anno);
+                for (i, slot) in leaf.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    match slot {
+                        Some(t) => {
+                            let _ = write!(out, "{:?}", t);
+                        }
+                        None => out.push('_'),
+                    }
+                }
+                out.push_str("]\n");
+            }
+            NStack::Node(node) => {
+                let _ = writeln!(out, "Node {:?}", anno);
+                for annotated in node.iter().flatten() {
+                    annotated.child().tree_block(
+                        &annotated.anno(),
+                        depth + 1,
+                        out,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Escape the characters with a special meaning in DOT record labels
+fn escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
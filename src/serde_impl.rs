@@ -0,0 +1,332 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! `serde` support, preserving the shape and annotations of the stack.
+//!
+//! A stack is serialized as its tree of blocks, with every leaf block
+//! holding its elements and every node block holding its children along with
+//! their annotations. Deserializing checks that the tree is a shape an
+//! [`NStack`] can take, and that every annotation matches its subtree. A root
+//! with a single child, as left by [`NStack::pop`], is skipped, so that no
+//! stack is serialized taller than it has to be.
+//!
+//! A [`Tombstoned`] stack is serialized the same way, except that every leaf
+//! holds all of its slots, holes included, so the positions of the elements
+//! are kept.
+
+use crate::edit::MIN;
+use crate::tombstone::Tombstoned;
+use crate::{NStack, NStackRef, N};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use serde::de::Error;
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use ranno::{Annotated, Annotation};
+
+impl<T, A> Serialize for NStack<T, A>
+where
+    T: Serialize,
+    A: Serialize + Annotation<Self>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut root = self;
+        while let NStack::Node([Some(child), None, None, None]) = root {
+            root = child.child();
+        }
+        Tree::<_, _, false>(root).serialize(serializer)
+    }
+}
+
+impl<T, A> Serialize for Tombstoned<T, A>
+where
+    T: Serialize,
+    A: Serialize + Annotation<NStack<T, A>>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Tree::<_, _, true>(self.stack()).serialize(serializer)
+    }
+}
+
+/// A tree of blocks, with `HOLES` set if its leaves are serialized with all
+/// their slots
+struct Tree<'a, T, A, const HOLES: bool>(&'a NStack<T, A>);
+
+impl<'a, T, A, const HOLES: bool> Serialize for Tree<'a, T, A, HOLES>
+where
+    T: Serialize,
+    A: Serialize + Annotation<NStack<T, A>>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            NStack::Leaf(leaf) => serializer.serialize_newtype_variant(
+                "NStack",
+                0,
+                "Leaf",
+                &Leaf::<_, HOLES>(leaf),
+            ),
+            NStack::Node(node) => serializer.serialize_newtype_variant(
+                "NStack",
+                1,
+                "Node",
+                &Node::<_, _, HOLES>(node),
+            ),
+        }
+    }
+}
+
+/// The elements of a leaf, serialized as a sequence, or all its slots as a
+/// sequence of options if `HOLES` is set
+struct Leaf<'a, T, const HOLES: bool>(&'a [Option<T>; N]);
+
+impl<'a, T, const HOLES: bool> Serialize for Leaf<'a, T, HOLES>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if HOLES {
+            serializer.collect_seq(self.0)
+        } else {
+            serializer.collect_seq(self.0.iter().flatten())
+        }
+    }
+}
+
+/// The children of a node, serialized as a sequence of tuples of their
+/// annotation and their subtree
+struct Node<'a, T, A, const HOLES: bool>(
+    &'a [Option<Annotated<NStackRef<T, A>, A>>; N],
+);
+
+impl<'a, T, A, const HOLES: bool> Serialize for Node<'a, T, A, HOLES>
+where
+    T: Serialize,
+    A: Serialize + Annotation<NStack<T, A>>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer
+            .collect_seq(self.0.iter().flatten().map(Child::<_, _, HOLES>))
+    }
+}
+
+struct Child<'a, T, A, const HOLES: bool>(&'a Annotated<NStackRef<T, A>, A>);
+
+impl<'a, T, A, const HOLES: bool> Serialize for Child<'a, T, A, HOLES>
+where
+    T: Serialize,
+    A: Serialize + Annotation<NStack<T, A>>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&*self.0.anno())?;
+        tuple.serialize_element(&Tree::<_, _, HOLES>(self.0.child()))?;
+        tuple.end()
+    }
+}
+
+/// The serialized form of a stack, before its shape is checked, with leaves
+/// holding either elements or slots
+#[derive(Deserialize)]
+#[serde(rename = "NStack")]
+enum Shape<L, A> {
+    Leaf(Vec<L>),
+    Node(Vec<(A, Shape<L, A>)>),
+}
+
+impl<'de, T, A> Deserialize<'de> for NStack<T, A>
+where
+    T: Deserialize<'de>,
+    A: Deserialize<'de> + Annotation<Self> + PartialEq,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let shape = Shape::<T, A>::deserialize(deserializer)?;
+        build_root(shape, false)
+    }
+}
+
+impl<'de, T, A> Deserialize<'de> for Tombstoned<T, A>
+where
+    T: Deserialize<'de>,
+    A: Deserialize<'de> + Annotation<NStack<T, A>> + PartialEq,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let shape = Shape::<Option<T>, A>::deserialize(deserializer)?;
+        build_root(shape, true).map(Tombstoned::from_packed)
+    }
+}
+
+/// The height and length of a subtree, and whether all its positions are
+/// taken
+struct Extent {
+    height: u32,
+    len: u64,
+    full: bool,
+}
+
+/// Build a stack from the serialized shape of its root, which must not be a
+/// node with a single child.
+fn build_root<T, A, L, E>(
+    shape: Shape<L, A>,
+    holes: bool,
+) -> Result<NStack<T, A>, E>
+where
+    A: Annotation<NStack<T, A>> + PartialEq,
+    L: Into<Option<T>>,
+    E: Error,
+{
+    if let Shape::Node(children) = &shape {
+        if children.len() == 1 {
+            return Err(E::custom("root has a single child"));
+        }
+    }
+
+    let (stack, _) = build(shape, holes, true)?;
+    Ok(stack)
+}
+
+/// Build a stack from its serialized shape, checking that it is left-packed,
+/// that all leaves are at the same depth, and that all annotations match.
+///
+/// Blocks on the `spine`, the path of last children from the root, may be
+/// under-full as left by [`NStack::push`] and [`NStack::pop`], but must not
+/// be empty. Every other block must hold at least [`MIN`] elements or
+/// children, as left by [`NStack::insert`] and [`NStack::remove`].
+///
+/// With `holes` set, every leaf must hold all its slots, and the stack must
+/// be packed instead: every block but the last of a node must be full, and
+/// the last must not be empty.
+fn build<T, A, L, E>(
+    shape: Shape<L, A>,
+    holes: bool,
+    spine: bool,
+) -> Result<(NStack<T, A>, Extent), E>
+where
+    A: Annotation<NStack<T, A>> + PartialEq,
+    L: Into<Option<T>>,
+    E: Error,
+{
+    match shape {
+        Shape::Leaf(slots) => {
+            if slots.len() > N || (holes && slots.len() != N) {
+                return Err(E::invalid_length(
+                    slots.len(),
+                    &if holes {
+                        "4 slots in a leaf"
+                    } else {
+                        "at most 4 elements in a leaf"
+                    },
+                ));
+            }
+
+            let mut leaf = [None, None, None, None];
+            for (slot, t) in leaf.iter_mut().zip(slots) {
+                *slot = t.into();
+            }
+            let len = leaf.iter().flatten().count() as u64;
+
+            let extent = Extent {
+                height: 1,
+                len,
+                full: true,
+            };
+            Ok((NStack::Leaf(leaf), extent))
+        }
+        Shape::Node(children) => {
+            if children.is_empty() || children.len() > N {
+                return Err(E::invalid_length(
+                    children.len(),
+                    &"between 1 and 4 children in a node",
+                ));
+            }
+
+            let count = children.len();
+            let mut node = [None, None, None, None];
+            let mut height = None;
+            let mut len = 0;
+            let mut full = count == N;
+
+            for (i, (anno, child)) in children.into_iter().enumerate() {
+                let last = i + 1 == count;
+                let on_spine = spine && last;
+                let occupied = match &child {
+                    Shape::Leaf(slots) => slots.len(),
+                    Shape::Node(children) => children.len(),
+                };
+                let (child, extent) = build(child, holes, on_spine)?;
+
+                let expected = *height.get_or_insert(extent.height);
+                if extent.height != expected {
+                    return Err(E::custom(format_args!(
+                        "child {} has height {}, expected {}",
+                        i, extent.height, expected
+                    )));
+                }
+
+                if holes && !last && !extent.full {
+                    return Err(E::custom(format_args!(
+                        "child {} is not full but not the last",
+                        i
+                    )));
+                }
+                if (holes || on_spine) && last && extent.len == 0 {
+                    return Err(E::custom(format_args!(
+                        "child {} is empty but is the last",
+                        i
+                    )));
+                }
+                if !holes && !on_spine && occupied < MIN {
+                    return Err(E::custom(format_args!(
+                        "child {} holds {} entries, expected at least {}",
+                        i, occupied, MIN
+                    )));
+                }
+
+                let annotated = Annotated::new(Box::new(child));
+                if *annotated.anno() != anno {
+                    return Err(E::custom(format_args!(
+                        "annotation of child {} does not match its subtree",
+                        i
+                    )));
+                }
+
+                node[i] = Some(annotated);
+                len += extent.len;
+                full &= extent.full;
+            }
+
+            let height = height.unwrap_or(0) + 1;
+            let extent = Extent { height, len, full };
+            Ok((NStack::Node(node), extent))
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Structural statistics of a stack.
+
+use crate::{NStack, N};
+
+use alloc::vec::Vec;
+
+use core::mem;
+
+/// Structural statistics of an [`NStack`], as returned by
+/// [`NStack::stats`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of levels in the tree
+    pub height: usize,
+    /// Number of `Leaf` blocks
+    pub leaves: usize,
+    /// Number of `Node` blocks
+    pub nodes: usize,
+    /// Occupancy of every level, starting at the root
+    pub levels: Vec<Level>,
+    /// Number of slots not holding an element or a child
+    pub empty_slots: usize,
+    /// Approximate number of bytes allocated on the heap for the blocks,
+    /// excluding any memory owned by the elements themselves
+    pub heap_bytes: usize,
+}
+
+/// Slot occupancy of one level of an [`NStack`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    /// Number of blocks on the level
+    pub blocks: usize,
+    /// Number of slots on the level holding an element or a child
+    pub occupied: usize,
+}
+
+impl Level {
+    /// Returns the number of slots on the level
+    pub fn slots(&self) -> usize {
+        self.blocks * N
+    }
+}
+
+impl<T, A> NStack<T, A> {
+    /// Returns structural statistics of the stack
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+
+        let mut level = alloc::vec![self];
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            let mut occupied = 0;
+
+            for block in &level {
+                match block {
+                    NStack::Leaf(leaf) => {
+                        stats.leaves += 1;
+                        occupied += leaf.iter().flatten().count();
+                    }
+                    NStack::Node(node) => {
+                        stats.nodes += 1;
+                        for annotated in node.iter().flatten() {
+                            occupied += 1;
+                            next.push(&**annotated.child());
+                        }
+                    }
+                }
+            }
+
+            stats.levels.push(Level {
+                blocks: level.len(),
+                occupied,
+            });
+            stats.empty_slots += level.len() * N - occupied;
+            level = next;
+        }
+
+        // every block but the root is boxed
+        let blocks = stats.leaves + stats.nodes;
+        stats.heap_bytes = (blocks - 1) * mem::size_of::<Self>();
+        stats.height = stats.levels.len();
+
+        stats
+    }
+}
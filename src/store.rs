@@ -0,0 +1,405 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Persistence of stacks, with subtrees loaded lazily.
+//!
+//! A stack is persisted one block at a time, with every node block holding
+//! the identifiers and annotations of its children. A [`StoredNStack`]
+//! opened from a store loads a block only when it is first accessed, so
+//! querying a stack only loads the blocks along the way. Loaded blocks are
+//! kept until [`StoredNStack::unload`] drops them, to be loaded again when
+//! next accessed.
+
+use crate::annotation::Cardinality;
+use crate::block::{Block, Blocks};
+use crate::encoding::{write_entry, Codec, Reader};
+use crate::iter::Iter;
+use crate::{NStack, N};
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::cell::OnceCell;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use microkelvin::{Branch, Child, ChildMut, Compound};
+use ranno::{Annotated, Annotation};
+
+type StoredNode<T, A> = [Option<Annotated<Box<StoredNStack<T, A>>, A>>; N];
+
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+
+/// Identifies a block in a [`Store`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id(u64);
+
+/// Storage for the blocks of a stack.
+pub trait Store {
+    /// Store the bytes, returning their identifier
+    fn put(&self, bytes: &[u8]) -> io::Result<Id>;
+
+    /// Returns the bytes stored under the identifier
+    fn get(&self, id: Id) -> io::Result<Vec<u8>>;
+}
+
+/// A [`Store`] keeping every block in its own file in a directory.
+#[derive(Debug)]
+pub struct DirStore {
+    path: PathBuf,
+    next: AtomicU64,
+}
+
+impl DirStore {
+    /// Open the store in the directory, creating it if it does not exist
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+
+        let mut next = 0;
+        for entry in fs::read_dir(&path)? {
+            let name = entry?.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| u64::from_str_radix(name, 16).ok());
+            if let Some(id) = id {
+                next = next.max(id + 1);
+            }
+        }
+
+        Ok(Self {
+            path,
+            next: AtomicU64::new(next),
+        })
+    }
+
+    fn file(&self, id: Id) -> PathBuf {
+        self.path.join(std::format!("{:016x}", id.0))
+    }
+}
+
+impl Store for DirStore {
+    fn put(&self, bytes: &[u8]) -> io::Result<Id> {
+        let id = Id(self.next.fetch_add(1, Ordering::Relaxed));
+        fs::write(self.file(id), bytes)?;
+        Ok(id)
+    }
+
+    fn get(&self, id: Id) -> io::Result<Vec<u8>> {
+        fs::read(self.file(id))
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    T: Codec,
+    A: Codec + Annotation<Self>,
+{
+    /// Write the blocks of the stack to the store, returning the identifier
+    /// to open it by with [`StoredNStack::open`]
+    pub fn persist(&self, store: &dyn Store) -> io::Result<Id> {
+        let mut buf = Vec::new();
+
+        match self {
+            NStack::Leaf(leaf) => {
+                buf.push(LEAF);
+                for t in leaf.iter().flatten() {
+                    write_entry(&mut buf, t);
+                }
+            }
+            NStack::Node(node) => {
+                buf.push(NODE);
+                for annotated in node.iter().flatten() {
+                    let id = annotated.child().persist(store)?;
+                    buf.extend_from_slice(&id.0.to_le_bytes());
+                    write_entry(&mut buf, &*annotated.anno());
+                }
+            }
+        }
+
+        store.put(&buf)
+    }
+}
+
+/// A stack opened from a [`Store`], loading its blocks on first access.
+///
+/// Loading happens while walking the stack, which can't fail, so a block
+/// that can't be read or decoded makes the walk panic. Use
+/// [`StoredNStack::try_nth`] or [`Lazy::try_load`] to get the error
+/// instead.
+pub enum StoredNStack<T, A> {
+    Leaf([Option<T>; N]),
+    Node(StoredNode<T, A>),
+    /// A block yet to be accessed, along with its annotation
+    Stored(Lazy<T, A>),
+}
+
+/// A block of a [`StoredNStack`] loaded on first access.
+pub struct Lazy<T, A> {
+    id: Id,
+    anno: A,
+    store: Arc<dyn Store>,
+    loaded: OnceCell<Box<StoredNStack<T, A>>>,
+}
+
+impl<T, A> Lazy<T, A> {
+    /// Returns the annotation of the block
+    pub fn anno(&self) -> &A {
+        &self.anno
+    }
+
+    /// Returns true if the block has been loaded
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.get().is_some()
+    }
+}
+
+impl<T, A> Lazy<T, A>
+where
+    T: Codec,
+    A: Codec + Annotation<StoredNStack<T, A>>,
+{
+    /// Returns the block, loading it if it isn't yet
+    pub fn try_load(&self) -> io::Result<&StoredNStack<T, A>> {
+        if let Some(block) = self.loaded.get() {
+            return Ok(block);
+        }
+
+        let block = load(&self.store, self.id)?;
+        Ok(self.loaded.get_or_init(|| Box::new(block)))
+    }
+
+    fn get(&self) -> &StoredNStack<T, A> {
+        self.try_load().unwrap_or_else(|err| {
+            panic!("failed loading block {:?}: {}", self.id, err)
+        })
+    }
+
+    fn take(&mut self) -> StoredNStack<T, A> {
+        self.get();
+        *self.loaded.take().expect("block loaded")
+    }
+}
+
+impl<T, A> StoredNStack<T, A>
+where
+    T: Codec,
+    A: Codec + Annotation<StoredNStack<T, A>>,
+{
+    /// Open the stack persisted under `id`, loading only its root block
+    pub fn open(store: Arc<dyn Store>, id: Id) -> io::Result<Self> {
+        load(&store, id)
+    }
+
+    /// Iterate over the elements of the stack, loading the blocks as they
+    /// are reached
+    pub fn iter(&self) -> Iter<'_, Self, A> {
+        Iter::new(self)
+    }
+
+    /// Construct a [`Branch`] pointing to the `nth` element, if any,
+    /// returning an error if a block on the way can't be loaded
+    pub fn try_nth(&self, index: u64) -> io::Result<Option<Branch<'_, Self, A>>>
+    where
+        A: Borrow<Cardinality>,
+    {
+        // load the blocks on the way first, so the walk doesn't have to
+        let mut block = self;
+        let mut offset = index;
+
+        loop {
+            block = match block {
+                StoredNStack::Leaf(_) => break,
+                StoredNStack::Node(node) => {
+                    let mut next = None;
+                    for annotated in node.iter().flatten() {
+                        let len = **(*annotated.anno()).borrow();
+                        if offset < len {
+                            next = Some(annotated.child());
+                            break;
+                        }
+                        offset -= len;
+                    }
+                    match next {
+                        Some(child) => child,
+                        None => break,
+                    }
+                }
+                StoredNStack::Stored(lazy) => lazy.try_load()?,
+            };
+        }
+
+        Ok(self.nth(index))
+    }
+
+    /// Drop all loaded blocks that haven't been edited, to be loaded again
+    /// when next accessed
+    pub fn unload(&mut self) {
+        match self {
+            StoredNStack::Leaf(_) => (),
+            StoredNStack::Node(node) => {
+                for annotated in node.iter_mut().flatten() {
+                    annotated.child_mut().unload();
+                }
+            }
+            StoredNStack::Stored(lazy) => {
+                lazy.loaded.take();
+            }
+        }
+    }
+}
+
+/// Load and decode the block stored under `id`
+fn load<T, A>(store: &Arc<dyn Store>, id: Id) -> io::Result<StoredNStack<T, A>>
+where
+    T: Codec,
+    A: Codec + Annotation<StoredNStack<T, A>>,
+{
+    let bytes = store.get(id)?;
+    let mut reader = Reader(&bytes);
+
+    let invalid = |_| io::Error::from(io::ErrorKind::InvalidData);
+
+    let block = match reader.take(1).map_err(invalid)?[0] {
+        LEAF => {
+            let mut leaf = [None, None, None, None];
+            for slot in leaf.iter_mut() {
+                if reader.0.is_empty() {
+                    break;
+                }
+                *slot = Some(read_entry(&mut reader)?);
+            }
+            StoredNStack::Leaf(leaf)
+        }
+        NODE => {
+            let mut node = [None, None, None, None];
+            for slot in node.iter_mut() {
+                if reader.0.is_empty() {
+                    break;
+                }
+                let id =
+                    Id(u64::from_le_bytes(reader.array().map_err(invalid)?));
+                let lazy = Lazy {
+                    id,
+                    anno: read_entry(&mut reader)?,
+                    store: Arc::clone(store),
+                    loaded: OnceCell::new(),
+                };
+                *slot =
+                    Some(Annotated::new(Box::new(StoredNStack::Stored(lazy))));
+            }
+            StoredNStack::Node(node)
+        }
+        _ => return Err(io::ErrorKind::InvalidData.into()),
+    };
+
+    if !reader.0.is_empty() {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+
+    Ok(block)
+}
+
+fn read_entry<V: Codec>(reader: &mut Reader) -> io::Result<V> {
+    let invalid = |_| io::Error::from(io::ErrorKind::InvalidData);
+
+    let size = u32::from_le_bytes(reader.array().map_err(invalid)?);
+    let bytes = reader.take(size as usize).map_err(invalid)?;
+
+    V::decode(bytes).ok_or_else(|| io::ErrorKind::InvalidData.into())
+}
+
+impl<T, A> Compound<A> for StoredNStack<T, A>
+where
+    T: Codec,
+    A: Codec + Annotation<Self>,
+{
+    type Leaf = T;
+
+    fn child(&self, index: usize) -> Child<'_, Self, A> {
+        match self {
+            StoredNStack::Leaf(leaf) => match leaf.get(index) {
+                Some(Some(t)) => Child::Leaf(t),
+                _ => Child::EndOfNode,
+            },
+            StoredNStack::Node(node) => match node.get(index) {
+                Some(Some(annotated)) => Child::Node(annotated),
+                _ => Child::EndOfNode,
+            },
+            StoredNStack::Stored(lazy) => lazy.get().child(index),
+        }
+    }
+
+    fn child_mut(&mut self, index: usize) -> ChildMut<'_, Self, A> {
+        // a block is edited in memory, so it can no longer be lazy
+        if let StoredNStack::Stored(lazy) = self {
+            *self = lazy.take();
+        }
+
+        match self {
+            StoredNStack::Leaf(leaf) => match leaf.get_mut(index) {
+                Some(Some(t)) => ChildMut::Leaf(t),
+                _ => ChildMut::EndOfNode,
+            },
+            StoredNStack::Node(node) => match node.get_mut(index) {
+                Some(Some(annotated)) => ChildMut::Node(annotated),
+                _ => ChildMut::EndOfNode,
+            },
+            StoredNStack::Stored(_) => unreachable!(),
+        }
+    }
+}
+
+impl<T, A> Blocks<T, A> for StoredNStack<T, A> {
+    fn block(&self) -> Block<'_, T, A, Self> {
+        match self {
+            StoredNStack::Leaf(leaf) => Block::Leaf(leaf),
+            StoredNStack::Node(node) => Block::Node(node),
+            StoredNStack::Stored(lazy) => Block::Stored(lazy.anno()),
+        }
+    }
+}
+
+impl<T, A> fmt::Debug for StoredNStack<T, A>
+where
+    T: fmt::Debug,
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoredNStack::Leaf(leaf) => {
+                f.debug_tuple("Leaf").field(leaf).finish()
+            }
+            StoredNStack::Node(node) => {
+                f.debug_tuple("Node").field(node).finish()
+            }
+            StoredNStack::Stored(lazy) => {
+                f.debug_tuple("Stored").field(lazy).finish()
+            }
+        }
+    }
+}
+
+impl<T, A> fmt::Debug for Lazy<T, A>
+where
+    T: fmt::Debug,
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy")
+            .field("id", &self.id)
+            .field("anno", &self.anno)
+            .field("loaded", &self.loaded.get())
+            .finish()
+    }
+}
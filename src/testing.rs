@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Generating random stacks for property testing.
+//!
+//! Stacks are generated from a [`History`] of pushes and pops, so they take
+//! the shapes that stacks reach in practice. The same history applied to a
+//! `Vec` gives the reference model of the stack.
+
+use crate::NStack;
+
+use alloc::vec::Vec;
+
+use ranno::Annotation;
+
+/// An edit of a stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<T> {
+    /// Push the element onto the stack
+    Push(T),
+    /// Pop an element off the stack
+    Pop,
+}
+
+/// A sequence of edits, starting from an empty stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History<T>(pub Vec<Op<T>>);
+
+impl<T> History<T>
+where
+    T: Clone,
+{
+    /// Build a stack by applying the edits to an empty stack
+    pub fn build<A>(&self) -> NStack<T, A>
+    where
+        A: Annotation<NStack<T, A>>,
+    {
+        let mut stack = NStack::new();
+        for op in &self.0 {
+            match op {
+                Op::Push(t) => stack.push(t.clone()),
+                Op::Pop => {
+                    stack.pop();
+                }
+            }
+        }
+        stack
+    }
+
+    /// Returns the reference model of the stack, by applying the edits to
+    /// an empty `Vec`
+    pub fn model(&self) -> Vec<T> {
+        let mut model = Vec::new();
+        for op in &self.0 {
+            match op {
+                Op::Push(t) => model.push(t.clone()),
+                Op::Pop => {
+                    model.pop();
+                }
+            }
+        }
+        model
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl {
+    use super::{History, Op};
+    use crate::NStack;
+
+    use arbitrary::{Arbitrary, Result, Unstructured};
+    use ranno::Annotation;
+
+    /// Pushes are three times as likely as pops
+    impl<'a, T> Arbitrary<'a> for Op<T>
+    where
+        T: Arbitrary<'a>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(match u.int_in_range(0..=3)? {
+                0 => Op::Pop,
+                _ => Op::Push(T::arbitrary(u)?),
+            })
+        }
+    }
+
+    impl<'a, T> Arbitrary<'a> for History<T>
+    where
+        T: Arbitrary<'a>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            u.arbitrary_iter()?.collect::<Result<_>>().map(History)
+        }
+
+        fn arbitrary_take_rest(u: Unstructured<'a>) -> Result<Self> {
+            u.arbitrary_take_rest_iter()?
+                .collect::<Result<_>>()
+                .map(History)
+        }
+    }
+
+    impl<'a, T, A> Arbitrary<'a> for NStack<T, A>
+    where
+        T: Arbitrary<'a> + Clone,
+        A: Annotation<Self>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            History::<T>::arbitrary(u).map(|history| history.build())
+        }
+
+        fn arbitrary_take_rest(u: Unstructured<'a>) -> Result<Self> {
+            History::<T>::arbitrary_take_rest(u).map(|history| history.build())
+        }
+    }
+}
+
+/// `proptest` strategies for stacks
+#[cfg(feature = "proptest")]
+pub mod strategy {
+    use super::{History, Op};
+    use crate::NStack;
+
+    use alloc::vec::Vec;
+
+    use core::fmt::Debug;
+    use core::ops::Range;
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use ranno::Annotation;
+
+    /// A strategy for a single edit, with pushes three times as likely as
+    /// pops
+    pub fn op<T>(
+        element: impl Strategy<Value = T>,
+    ) -> impl Strategy<Value = Op<T>>
+    where
+        T: Debug,
+    {
+        prop_oneof![
+            3 => element.prop_map(Op::Push),
+            1 => Just(()).prop_map(|_| Op::Pop),
+        ]
+    }
+
+    /// A strategy for histories with a number of edits in `len`
+    pub fn history<T>(
+        element: impl Strategy<Value = T>,
+        len: Range<usize>,
+    ) -> impl Strategy<Value = History<T>>
+    where
+        T: Debug,
+    {
+        vec(op(element), len).prop_map(History)
+    }
+
+    /// A strategy for stacks built from a history with a number of edits in
+    /// `len`, along with their reference model
+    pub fn nstack<T, A>(
+        element: impl Strategy<Value = T>,
+        len: Range<usize>,
+    ) -> impl Strategy<Value = (NStack<T, A>, Vec<T>)>
+    where
+        T: Debug + Clone,
+        A: Debug + Annotation<NStack<T, A>>,
+    {
+        history(element, len)
+            .prop_map(|history| (history.build(), history.model()))
+    }
+}
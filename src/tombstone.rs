@@ -0,0 +1,449 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Removing elements without shifting the positions of the others.
+//!
+//! A stack built by pushing and popping is packed: every block but the last
+//! of a node is full, so the position of an element follows from the slots
+//! leading to it. A [`Tombstoned`] stack keeps its stack packed, and
+//! [`Tombstoned::remove_hole`] clears a slot in place, leaving a hole
+//! behind, so the other elements keep their positions until the holes are
+//! squeezed out by [`Tombstoned::compact`].
+//!
+//! Holes never leave a [`Tombstoned`] stack: the inner [`NStack`] is only
+//! handed out by [`Tombstoned::into_inner`], which compacts it first.
+
+use crate::annotation::Cardinality;
+use crate::{NStack, N};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::borrow::Borrow;
+use core::mem;
+
+use microkelvin::{Branch, BranchMut, Child, Step, Walk, Walker};
+use ranno::{Annotated, Annotation};
+
+/// A stack whose elements can be removed without shifting the positions of
+/// the others.
+///
+/// The stack is kept packed, as left by pushing and popping, so the
+/// position of every element is known from the shape of the tree. Elements
+/// are looked up either by position, counting the holes left by
+/// [`remove_hole`], or by rank, skipping them.
+///
+/// [`remove_hole`]: Tombstoned::remove_hole
+#[derive(Debug)]
+pub struct Tombstoned<T, A> {
+    stack: NStack<T, A>,
+}
+
+impl<T, A> Tombstoned<T, A> {
+    /// Creates a new empty Tombstoned stack
+    pub const fn new() -> Self {
+        Self {
+            stack: NStack::new(),
+        }
+    }
+
+    /// Wraps a stack known to be packed, holes included
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_packed(stack: NStack<T, A>) -> Self {
+        Self { stack }
+    }
+
+    /// Returns the inner stack, holes included
+    #[cfg(feature = "serde")]
+    pub(crate) fn stack(&self) -> &NStack<T, A> {
+        &self.stack
+    }
+
+    /// Returns the number of positions in the stack, counting both the
+    /// elements and the holes before the last element
+    pub fn positional_len(&self) -> u64 {
+        self.stack.positional_len()
+    }
+
+    /// Iterate over the elements of the stack, from the bottom up, skipping
+    /// the holes
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter {
+            path: alloc::vec![(&self.stack, 0)],
+        }
+    }
+}
+
+impl<T, A> Tombstoned<T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    /// Pushes a new element onto the stack, after the last position
+    pub fn push(&mut self, t: T) {
+        let position = self.positional_len();
+        if let Err(t) = self.stack.put_slot(position, t) {
+            let old_root = mem::take(&mut self.stack);
+            self.stack = NStack::Node([
+                Some(Annotated::new(Box::new(old_root))),
+                None,
+                None,
+                None,
+            ]);
+            if self.stack.put_slot(position, t).is_err() {
+                unreachable!("a grown root has room for another element");
+            }
+        }
+    }
+
+    /// Pops the element at the last position off the stack, also dropping
+    /// the holes left before it.
+    ///
+    /// Returns the popped element, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let position = self.positional_len().checked_sub(1)?;
+        let t = self.stack.take_slot(position);
+
+        if self.stack.trim() {
+            self.stack = NStack::new();
+        }
+
+        // a root left with a single child is replaced by it, which keeps
+        // the positions of its elements
+        while let NStack::Node([first @ Some(_), None, None, None]) =
+            &mut self.stack
+        {
+            let child = first.take().expect("child exists").split().0;
+            self.stack = *child;
+        }
+
+        t
+    }
+
+    /// Construct a [`Branch`] pointing to the element at `position`, if
+    /// any.
+    ///
+    /// Holes are counted, so returns `None` for a hole.
+    pub fn nth_position(
+        &self,
+        position: u64,
+    ) -> Option<Branch<'_, NStack<T, A>, A>> {
+        Branch::walk(&self.stack, Position(position))
+    }
+
+    /// Construct a [`BranchMut`] pointing to the element at `position`, if
+    /// any.
+    ///
+    /// Holes are counted, so returns `None` for a hole.
+    pub fn nth_position_mut(
+        &mut self,
+        position: u64,
+    ) -> Option<BranchMut<'_, NStack<T, A>, A>> {
+        BranchMut::walk(&mut self.stack, Position(position))
+    }
+
+    /// Removes the element at `position` and returns it, leaving a hole in
+    /// its place so the elements after it keep their positions.
+    ///
+    /// Removing the last element is the same as [`pop`], and also drops the
+    /// holes left before it. Returns `None` if `position` is out of bounds
+    /// or already a hole.
+    ///
+    /// [`pop`]: Tombstoned::pop
+    pub fn remove_hole(&mut self, position: u64) -> Option<T> {
+        let len = self.positional_len();
+        if position >= len {
+            return None;
+        }
+        if position + 1 == len {
+            return self.pop();
+        }
+        self.stack.take_slot(position)
+    }
+
+    /// Squeezes the holes out of the stack, shifting the elements after
+    /// them to lower positions.
+    ///
+    /// The stack is re-packed from its elements, as by [`NStack::rebuild`],
+    /// so it is left at its minimal height.
+    pub fn compact(&mut self) {
+        self.stack.rebuild();
+    }
+
+    /// Consume the structure, returning the inner stack with its holes
+    /// squeezed out
+    pub fn into_inner(mut self) -> NStack<T, A> {
+        self.compact();
+        self.stack
+    }
+}
+
+impl<T, A> Tombstoned<T, A>
+where
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    /// Returns the number of elements in the stack, not counting the holes
+    pub fn len(&self) -> u64 {
+        **A::from_child(&self.stack).borrow()
+    }
+
+    /// Returns true if the stack holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Construct a [`Branch`] pointing to the `nth` element, if any.
+    ///
+    /// Holes are skipped, so `index` is the rank of the element among the
+    /// others.
+    pub fn nth(&self, index: u64) -> Option<Branch<'_, NStack<T, A>, A>> {
+        Branch::walk(&self.stack, Rank(index))
+    }
+
+    /// Construct a [`BranchMut`] pointing to the `nth` element, if any.
+    ///
+    /// Holes are skipped, so `index` is the rank of the element among the
+    /// others.
+    pub fn nth_mut(
+        &mut self,
+        index: u64,
+    ) -> Option<BranchMut<'_, NStack<T, A>, A>> {
+        BranchMut::walk(&mut self.stack, Rank(index))
+    }
+}
+
+impl<T, A> Default for Tombstoned<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A> From<NStack<T, A>> for Tombstoned<T, A>
+where
+    A: Annotation<NStack<T, A>>,
+{
+    /// Wraps the stack, re-packing it first since it may have been left
+    /// relaxed by [`NStack::insert`] or [`NStack::remove`].
+    fn from(mut stack: NStack<T, A>) -> Self {
+        stack.rebuild();
+        Self { stack }
+    }
+}
+
+/// Iterator over the elements of a [`Tombstoned`] stack.
+///
+/// Created by [`Tombstoned::iter`].
+pub struct Iter<'a, T, A> {
+    // blocks from the root to the current leaf, and the next slot index
+    path: Vec<(&'a NStack<T, A>, usize)>,
+}
+
+impl<'a, T, A> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (block, i) = self.path.last_mut()?;
+            let block: &'a NStack<T, A> = block;
+
+            let index = *i;
+            *i += 1;
+
+            match block {
+                NStack::Leaf(leaf) => match leaf.get(index) {
+                    Some(Some(t)) => return Some(t),
+                    // a hole
+                    Some(None) => (),
+                    None => {
+                        self.path.pop();
+                    }
+                },
+                NStack::Node(node) => match node.get(index) {
+                    Some(Some(annotated)) => {
+                        self.path.push((annotated.child(), 0))
+                    }
+                    _ => {
+                        self.path.pop();
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<T, A> NStack<T, A> {
+    /// Returns the number of positions in a packed stack
+    fn positional_len(&self) -> u64 {
+        match self {
+            NStack::Leaf(leaf) => leaf
+                .iter()
+                .rposition(Option::is_some)
+                .map_or(0, |i| i as u64 + 1),
+            NStack::Node(node) => {
+                match node.iter().rposition(Option::is_some) {
+                    Some(last) => {
+                        let child = node[last].as_ref().expect("child exists");
+                        last as u64 * self.child_capacity()
+                            + child.child().positional_len()
+                    }
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    /// Returns the number of positions each child of the block holds
+    fn child_capacity(&self) -> u64 {
+        match self {
+            NStack::Leaf(_) => 1,
+            NStack::Node(node) => match &node[0] {
+                Some(annotated) => {
+                    N as u64 * annotated.child().child_capacity()
+                }
+                None => 1,
+            },
+        }
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self>,
+{
+    fn take_slot(&mut self, position: u64) -> Option<T> {
+        let capacity = self.child_capacity();
+        match self {
+            NStack::Leaf(leaf) => leaf.get_mut(position as usize)?.take(),
+            NStack::Node(node) => {
+                let annotated = node.get_mut((position / capacity) as usize)?;
+                annotated
+                    .as_mut()?
+                    .child_mut()
+                    .take_slot(position % capacity)
+            }
+        }
+    }
+
+    /// Write `t` at `position`, the one after the last, adding the blocks
+    /// leading to it if needed. Returns `t` back if the block has no room
+    /// for it.
+    fn put_slot(&mut self, position: u64, t: T) -> Result<(), T> {
+        let capacity = self.child_capacity();
+        match self {
+            NStack::Leaf(leaf) => match leaf.get_mut(position as usize) {
+                Some(slot) => {
+                    *slot = Some(t);
+                    Ok(())
+                }
+                None => Err(t),
+            },
+            NStack::Node(node) => {
+                let i = (position / capacity) as usize;
+                if i >= N {
+                    return Err(t);
+                }
+                if let Some(annotated) = &mut node[i] {
+                    return annotated
+                        .child_mut()
+                        .put_slot(position % capacity, t);
+                }
+
+                // a new block starts at the first position it holds, so its
+                // element is the first of its leftmost leaf
+                let depth = node[0].as_ref().map_or(0, |a| a.child().height());
+                let mut block = NStack::Leaf([Some(t), None, None, None]);
+                for _ in 1..depth {
+                    block = NStack::Node([
+                        Some(Annotated::new(Box::new(block))),
+                        None,
+                        None,
+                        None,
+                    ]);
+                }
+                node[i] = Some(Annotated::new(Box::new(block)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop the blocks at the end left empty by holes, returning true if the
+    /// whole block is empty
+    fn trim(&mut self) -> bool {
+        match self {
+            NStack::Leaf(leaf) => leaf.iter().all(Option::is_none),
+            NStack::Node(node) => {
+                for i in (0..N).rev() {
+                    if let Some(annotated) = &mut node[i] {
+                        if !annotated.child_mut().trim() {
+                            return false;
+                        }
+                        node[i] = None;
+                    }
+                }
+                true
+            }
+        }
+    }
+}
+
+struct Position(u64);
+
+impl<T, A> Walker<NStack<T, A>, A> for Position
+where
+    A: Annotation<NStack<T, A>>,
+{
+    fn walk(&mut self, walk: Walk<NStack<T, A>, A>) -> Step {
+        let capacity = match walk.child(0) {
+            Child::Node(annotated) => {
+                N as u64 * annotated.child().child_capacity()
+            }
+            _ => 1,
+        };
+
+        let i = self.0 / capacity;
+        self.0 %= capacity;
+
+        if i >= N as u64 {
+            return Step::Abort;
+        }
+
+        match walk.child(i as usize) {
+            Child::Leaf(_) => Step::Found(i as usize),
+            Child::Node(_) => Step::Into(i as usize),
+            _ => Step::Abort,
+        }
+    }
+}
+
+/// Walks to the element of the given rank, looking past the holes that end
+/// the children of a leaf early
+struct Rank(u64);
+
+impl<T, A> Walker<NStack<T, A>, A> for Rank
+where
+    A: Annotation<NStack<T, A>> + Borrow<Cardinality>,
+{
+    fn walk(&mut self, walk: Walk<NStack<T, A>, A>) -> Step {
+        for i in 0..N {
+            match walk.child(i) {
+                Child::Leaf(_) => {
+                    if self.0 == 0 {
+                        return Step::Found(i);
+                    }
+                    self.0 -= 1;
+                }
+                Child::Node(node) => {
+                    let c = **Borrow::<Cardinality>::borrow(&*node.anno());
+                    if self.0 < c {
+                        return Step::Into(i);
+                    }
+                    self.0 -= c;
+                }
+                Child::Empty | Child::EndOfNode => (),
+            }
+        }
+        Step::Abort
+    }
+}
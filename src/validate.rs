@@ -0,0 +1,169 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Checking and repairing the shape of a stack.
+
+use crate::{NStack, N};
+
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::mem;
+
+use ranno::Annotation;
+
+/// A broken invariant of an [`NStack`], along with the path of child
+/// indices leading from the root to the offending block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// The block has an empty slot before an occupied one
+    NotLeftPacked { path: Vec<usize> },
+    /// The node has no children
+    EmptyNode { path: Vec<usize> },
+    /// The block is at a different height than its first sibling
+    UnevenDepth { path: Vec<usize> },
+    /// The block is empty, but is not the first of its siblings
+    Empty { path: Vec<usize> },
+    /// The annotation of the block differs from a freshly computed one
+    StaleAnnotation { path: Vec<usize> },
+}
+
+impl InvariantError {
+    /// Returns the path of child indices leading to the offending block
+    pub fn path(&self) -> &[usize] {
+        match self {
+            InvariantError::NotLeftPacked { path }
+            | InvariantError::EmptyNode { path }
+            | InvariantError::UnevenDepth { path }
+            | InvariantError::Empty { path }
+            | InvariantError::StaleAnnotation { path } => path,
+        }
+    }
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            InvariantError::NotLeftPacked { .. } => "is not left-packed",
+            InvariantError::EmptyNode { .. } => "is a node without children",
+            InvariantError::UnevenDepth { .. } => "has uneven depth",
+            InvariantError::Empty { .. } => "is empty but not the first",
+            InvariantError::StaleAnnotation { .. } => "has a stale annotation",
+        };
+        write!(f, "block at {:?} {}", self.path(), what)
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self> + PartialEq,
+{
+    /// Check the invariants of the stack.
+    ///
+    /// Every block must be left-packed, all leaves must be at the same
+    /// depth, every block but the first of a node must be non-empty and
+    /// every stored annotation must equal one freshly computed from its
+    /// block. Blocks may be under-full, as left by [`NStack::insert`] and
+    /// [`NStack::remove`].
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.check(&mut Vec::new()).map(|_| ())
+    }
+
+    /// Returns the height and length of a valid stack
+    fn check(
+        &self,
+        path: &mut Vec<usize>,
+    ) -> Result<(u32, u64), InvariantError> {
+        match self {
+            NStack::Leaf(leaf) => {
+                if !is_left_packed(leaf) {
+                    return Err(InvariantError::NotLeftPacked {
+                        path: path.clone(),
+                    });
+                }
+                Ok((1, leaf.iter().flatten().count() as u64))
+            }
+            NStack::Node(node) => {
+                if !is_left_packed(node) {
+                    return Err(InvariantError::NotLeftPacked {
+                        path: path.clone(),
+                    });
+                }
+
+                let count = node.iter().flatten().count();
+                if count == 0 {
+                    return Err(InvariantError::EmptyNode {
+                        path: path.clone(),
+                    });
+                }
+
+                let mut height = None;
+                let mut len = 0;
+
+                for (i, annotated) in node.iter().flatten().enumerate() {
+                    path.push(i);
+
+                    let child = &**annotated.child();
+                    let (h, l) = child.check(path)?;
+
+                    if h != *height.get_or_insert(h) {
+                        return Err(InvariantError::UnevenDepth {
+                            path: path.clone(),
+                        });
+                    }
+                    if i > 0 && l == 0 {
+                        return Err(InvariantError::Empty {
+                            path: path.clone(),
+                        });
+                    }
+                    if *annotated.anno() != A::from_child(child) {
+                        return Err(InvariantError::StaleAnnotation {
+                            path: path.clone(),
+                        });
+                    }
+
+                    path.pop();
+                    len += l;
+                }
+
+                Ok((height.unwrap_or(0) + 1, len))
+            }
+        }
+    }
+}
+
+impl<T, A> NStack<T, A>
+where
+    A: Annotation<Self>,
+{
+    /// Re-pack the stack from its elements, in order, repairing any broken
+    /// invariant and recomputing all annotations
+    pub fn rebuild(&mut self) {
+        let mut elements = Vec::new();
+        mem::take(self).drain_into(&mut elements);
+
+        for t in elements {
+            self.push(t);
+        }
+    }
+
+    pub(crate) fn drain_into(self, elements: &mut Vec<T>) {
+        match self {
+            NStack::Leaf(leaf) => elements.extend(leaf.into_iter().flatten()),
+            NStack::Node(node) => {
+                for annotated in node.into_iter().flatten() {
+                    let (child, _) = annotated.split();
+                    child.drain_into(elements);
+                }
+            }
+        }
+    }
+}
+
+fn is_left_packed<E>(block: &[Option<E>; N]) -> bool {
+    let occupied = block.iter().take_while(|e| e.is_some()).count();
+    block[occupied..].iter().all(Option::is_none)
+}
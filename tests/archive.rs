@@ -0,0 +1,202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+#![cfg(feature = "rkyv")]
+
+use nstack::annotation::{ArchivedMaxKey, Cardinality, MaxKey};
+use nstack::tombstone::Tombstoned;
+use nstack::NStack;
+use ranno::Annotation;
+use rkyv::rancor::Error;
+
+#[test]
+fn archived_nth() {
+    for n in [0, 1, 4, 5, 17, 64, 65, 1000] {
+        let mut nt = NStack::<u64, Cardinality>::new();
+
+        for i in 0..n {
+            nt.push(i);
+        }
+
+        let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+        let archived = rkyv::access::<
+            rkyv::Archived<NStack<u64, Cardinality>>,
+            Error,
+        >(&bytes)
+        .expect("valid archive");
+
+        assert_eq!(archived.len(), n);
+        assert_eq!(*archived.annotation(), n);
+
+        for i in 0..n {
+            assert_eq!(*archived.nth(i).expect("Some(_)"), i);
+        }
+        assert!(archived.nth(n).is_none());
+
+        let nt: NStack<u64, Cardinality> =
+            rkyv::deserialize::<_, Error>(archived).expect("deserialized");
+
+        assert_eq!(Cardinality::from_child(&nt), n);
+        for i in 0..n {
+            assert_eq!(*nt.nth(i).expect("Some(_)"), i);
+        }
+    }
+}
+
+#[test]
+fn archived_after_pop() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+
+    for i in 0..100 {
+        nt.push(i);
+    }
+    for _ in 0..90 {
+        nt.pop();
+    }
+
+    let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+    let archived =
+        rkyv::access::<rkyv::Archived<NStack<u64, Cardinality>>, Error>(&bytes)
+            .expect("valid archive");
+
+    assert_eq!(archived.len(), 10);
+    assert_eq!(
+        archived.iter().map(|i| i.to_native()).collect::<Vec<_>>(),
+        (0..10).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn archived_max_key() {
+    for n in [1, 4, 5, 17, 100, 1000] {
+        let mut nt = NStack::<u64, MaxKey<u64>>::new();
+
+        for i in 0..n {
+            nt.push((i * 7919) % n);
+        }
+
+        let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+        let archived = rkyv::access::<
+            rkyv::Archived<NStack<u64, MaxKey<u64>>>,
+            Error,
+        >(&bytes)
+        .expect("valid archive");
+
+        let max = archived.max_key::<u64>().expect("Some(_)");
+        assert_eq!(*max, *nt.max_key::<u64>().expect("Some(_)"));
+        assert_eq!(
+            *archived.annotation(),
+            ArchivedMaxKey::Maximum((n - 1).into())
+        );
+    }
+
+    let nt = NStack::<u64, MaxKey<u64>>::new();
+    let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+    let archived =
+        rkyv::access::<rkyv::Archived<NStack<u64, MaxKey<u64>>>, Error>(&bytes)
+            .expect("valid archive");
+
+    assert!(archived.max_key::<u64>().is_none());
+}
+
+#[test]
+fn archived_after_insert() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+    let mut model = Vec::new();
+
+    for i in 0..200 {
+        let index = (i * 7) % (model.len() as u64 + 1);
+        nt.insert(index, i);
+        model.insert(index as usize, i);
+    }
+    for i in 0..50 {
+        let index = (i * 13) % model.len() as u64;
+        assert_eq!(nt.remove(index), model.remove(index as usize));
+    }
+
+    let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+    let archived =
+        rkyv::access::<rkyv::Archived<NStack<u64, Cardinality>>, Error>(&bytes)
+            .expect("valid archive");
+
+    assert_eq!(*archived.annotation(), model.len() as u64);
+    assert_eq!(
+        archived.iter().map(|i| i.to_native()).collect::<Vec<_>>(),
+        model
+    );
+}
+
+#[test]
+fn archived_holes() {
+    let mut nt = Tombstoned::<u64, Cardinality>::new();
+
+    for i in 0..20 {
+        nt.push(i);
+    }
+    for i in [0, 4, 5, 6, 7, 13] {
+        nt.remove_hole(i);
+    }
+
+    let nt = nt.into_inner();
+    let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+    let archived =
+        rkyv::access::<rkyv::Archived<NStack<u64, Cardinality>>, Error>(&bytes)
+            .expect("valid archive");
+
+    // the holes are squeezed out before archiving
+    assert_eq!(
+        archived.iter().map(|i| i.to_native()).collect::<Vec<_>>(),
+        nt.iter().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(*archived.annotation(), 14);
+}
+
+#[test]
+fn archived_invalid_layout() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+
+    for i in 0..17 {
+        nt.push(i);
+    }
+
+    let mut bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+
+    // the root is at the end of the buffer, with the height as first field
+    let root = bytes.len()
+        - core::mem::size_of::<rkyv::Archived<NStack<u64, Cardinality>>>();
+    bytes[root] = 1;
+
+    assert!(
+        rkyv::access::<rkyv::Archived<NStack<u64, Cardinality>>, Error>(&bytes)
+            .is_err()
+    );
+}
+
+#[test]
+fn archived_invalid_height() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+
+    for i in 0..17 {
+        nt.push(i);
+    }
+
+    let bytes = rkyv::to_bytes::<Error>(&nt).expect("serialized");
+    let root = bytes.len()
+        - core::mem::size_of::<rkyv::Archived<NStack<u64, Cardinality>>>();
+
+    // taller than the number of blocks, or than the levels they make up
+    for height in [u32::MAX, 4] {
+        let mut bytes = bytes.clone();
+        bytes[root..root + 4].copy_from_slice(&height.to_le_bytes());
+
+        assert!(
+            rkyv::access::<rkyv::Archived<NStack<u64, Cardinality>>, Error>(
+                &bytes
+            )
+            .is_err()
+        );
+    }
+}
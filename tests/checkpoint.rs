@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use nstack::annotation::Cardinality;
+use nstack::checkpoint::Checkpointed;
+use nstack::NStack;
+use ranno::Annotation;
+
+fn same_shape<T: PartialEq>(
+    a: &NStack<T, Cardinality>,
+    b: &NStack<T, Cardinality>,
+) -> bool {
+    match (a, b) {
+        (NStack::Leaf(a), NStack::Leaf(b)) => a == b,
+        (NStack::Node(a), NStack::Node(b)) => {
+            a.iter().zip(b.iter()).all(|pair| match pair {
+                (Some(a), Some(b)) => {
+                    *a.anno() == *b.anno() && same_shape(a.child(), b.child())
+                }
+                (None, None) => true,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
+fn filled(n: u64) -> NStack<u64, Cardinality> {
+    let mut nt = NStack::new();
+    for i in 0..n {
+        nt.push(i);
+    }
+    nt
+}
+
+#[test]
+fn rollback() {
+    for n in [0, 1, 4, 16, 17, 64, 100] {
+        let mut nt = Checkpointed::from(filled(n));
+
+        let id = nt.checkpoint();
+
+        for i in 0..n / 2 {
+            *nt.nth_mut(i).expect("Some(_)") += 1000;
+        }
+        for _ in 0..n / 3 {
+            nt.pop();
+        }
+        for i in 0..n + 5 {
+            nt.push(i);
+        }
+
+        nt.rollback(id);
+
+        assert!(same_shape(&nt, &filled(n)));
+        assert_eq!(Cardinality::from_child(&*nt), n);
+    }
+}
+
+#[test]
+fn nested() {
+    let mut nt = Checkpointed::from(filled(16));
+
+    let outer = nt.checkpoint();
+    nt.push(16);
+
+    let inner = nt.checkpoint();
+    nt.push(17);
+    *nt.nth_mut(0).expect("Some(_)") = 100;
+
+    nt.rollback(inner);
+    assert!(same_shape(&nt, &filled(17)));
+
+    let inner = nt.checkpoint();
+    nt.pop();
+    nt.commit(inner);
+    assert_eq!(Cardinality::from_child(&*nt), 16);
+
+    nt.push(16);
+    nt.push(17);
+    nt.rollback(outer);
+    assert!(same_shape(&nt, &filled(16)));
+}
+
+#[test]
+#[should_panic]
+fn released() {
+    let mut nt = Checkpointed::from(filled(16));
+
+    let outer = nt.checkpoint();
+    let inner = nt.checkpoint();
+
+    nt.commit(outer);
+    nt.rollback(inner);
+}
+
+#[test]
+#[should_panic(expected = "checkpoint was already released")]
+fn released_then_reused() {
+    let mut nt = Checkpointed::from(filled(16));
+
+    let first = nt.checkpoint();
+    nt.commit(first);
+
+    let second = nt.checkpoint();
+    assert_ne!(first, second);
+
+    nt.push(16);
+    nt.rollback(first);
+}
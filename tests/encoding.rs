@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use nstack::annotation::Cardinality;
+use nstack::encoding::{Codec, DecodeError};
+use nstack::NStack;
+use ranno::Annotation;
+
+fn stack(elements: impl IntoIterator<Item = u32>) -> NStack<u32, Cardinality> {
+    let mut nt = NStack::new();
+    for t in elements {
+        nt.push(t);
+    }
+    nt
+}
+
+#[rustfmt::skip]
+const GOLDEN_EMPTY: &[u8] = &[
+    0x01, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[rustfmt::skip]
+const GOLDEN_ONE: &[u8] = &[
+    0x01, 0x01,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
+];
+
+#[rustfmt::skip]
+const GOLDEN_FIVE: &[u8] = &[
+    0x01, 0x02,
+    0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+];
+
+#[test]
+fn golden_vectors() {
+    let vectors = [
+        (stack([]), GOLDEN_EMPTY),
+        (stack([0xdeadbeef]), GOLDEN_ONE),
+        (stack(0..5), GOLDEN_FIVE),
+    ];
+
+    for (nt, golden) in vectors {
+        assert_eq!(nt.encode(), golden);
+
+        let decoded =
+            NStack::<u32, Cardinality>::decode(golden).expect("decoded");
+        assert_eq!(decoded.encode(), golden);
+    }
+}
+
+#[test]
+fn independent_of_shape() {
+    let mut popped = stack(0..100);
+    for _ in 0..95 {
+        popped.pop();
+    }
+
+    assert_eq!(popped.encode(), GOLDEN_FIVE);
+    assert_eq!(stack(0..5).encode(), GOLDEN_FIVE);
+}
+
+#[test]
+fn round_trip() {
+    for n in [0, 1, 4, 5, 16, 17, 1000] {
+        let nt = stack(0..n);
+        let decoded =
+            NStack::<u32, Cardinality>::decode(&nt.encode()).expect("decoded");
+
+        assert_eq!(Cardinality::from_child(&decoded), n as u64);
+        for i in 0..n {
+            assert_eq!(*decoded.nth(i as u64).expect("Some(_)"), i);
+        }
+    }
+}
+
+#[test]
+fn rejects_invalid() {
+    let decode = NStack::<u32, Cardinality>::decode;
+
+    let mut bytes = GOLDEN_ONE.to_vec();
+    bytes.push(0);
+    assert_eq!(decode(&bytes).err(), Some(DecodeError::TrailingBytes));
+
+    assert_eq!(
+        decode(&GOLDEN_ONE[..GOLDEN_ONE.len() - 1]).err(),
+        Some(DecodeError::UnexpectedEnd)
+    );
+
+    let mut bytes = GOLDEN_ONE.to_vec();
+    bytes[0] = 2;
+    assert_eq!(
+        decode(&bytes).err(),
+        Some(DecodeError::UnsupportedVersion(2))
+    );
+
+    let mut bytes = GOLDEN_FIVE.to_vec();
+    bytes[1] = 3;
+    assert_eq!(
+        decode(&bytes).err(),
+        Some(DecodeError::NonCanonicalHeight {
+            height: 3,
+            expected: 2
+        })
+    );
+
+    // an element with a wrong length
+    let mut bytes = GOLDEN_ONE.to_vec();
+    bytes[10] = 3;
+    bytes.pop();
+    assert_eq!(decode(&bytes).err(), Some(DecodeError::InvalidElement(0)));
+}
+
+/// A codec accepting any non-zero byte as `true`, but encoding it as `1`
+#[derive(Debug)]
+struct Flag(bool);
+
+impl Codec for Flag {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.0 as u8);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [byte] => Some(Flag(*byte != 0)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn rejects_non_canonical_element() {
+    let mut nt = NStack::<Flag, Cardinality>::new();
+    nt.push(Flag(false));
+    nt.push(Flag(true));
+
+    let mut bytes = nt.encode();
+    assert!(NStack::<Flag, Cardinality>::decode(&bytes).is_ok());
+
+    *bytes.last_mut().expect("Some(_)") = 2;
+    assert_eq!(
+        NStack::<Flag, Cardinality>::decode(&bytes).err(),
+        Some(DecodeError::NonCanonicalElement(1))
+    );
+}
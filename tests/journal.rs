@@ -0,0 +1,105 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use nstack::annotation::Cardinality;
+use nstack::journal::{Journal, JournalError, Journaled};
+use nstack::NStack;
+use ranno::Annotation;
+
+fn assert_same(stack: &NStack<u64, Cardinality>, expected: &[u64]) {
+    assert_eq!(Cardinality::from_child(stack), expected.len() as u64);
+    for (i, t) in expected.iter().enumerate() {
+        assert_eq!(*stack.nth(i as u64).expect("Some(_)"), *t);
+    }
+}
+
+#[test]
+fn replay() {
+    let mut journaled = Journaled::<u64, Cardinality>::new();
+    let mut model = Vec::new();
+
+    for i in 0..200 {
+        journaled.push(i);
+        model.push(i);
+    }
+    for _ in 0..30 {
+        assert_eq!(journaled.pop(), model.pop());
+    }
+    journaled.truncate(100);
+    model.truncate(100);
+
+    for i in (0..100).step_by(7) {
+        *journaled.nth_mut(i).expect("Some(_)") += 1000;
+        model[i as usize] += 1000;
+    }
+    for i in 0..10 {
+        journaled.push(i);
+        model.push(i);
+    }
+
+    let (stack, journal) = journaled.into_inner();
+    assert_same(&stack, &model);
+
+    let replayed =
+        NStack::<u64, Cardinality>::replay(&journal).expect("replayed");
+    assert_same(&replayed, &model);
+
+    // the journal survives a round trip through its bytes
+    let journal = Journal::from_bytes(journal.as_bytes().to_vec());
+    let replayed =
+        NStack::<u64, Cardinality>::replay(&journal).expect("replayed");
+    assert_same(&replayed, &model);
+}
+
+#[test]
+fn apply_to_snapshot() {
+    let mut journaled = Journaled::<u64, Cardinality>::new();
+
+    for i in 0..50 {
+        journaled.push(i);
+    }
+
+    let snapshot = journaled.encode();
+    journaled.take_journal();
+
+    journaled.truncate(20);
+    *journaled.nth_mut(3).expect("Some(_)") = 42;
+    journaled.push(7);
+
+    let mut recovered =
+        NStack::<u64, Cardinality>::decode(&snapshot).expect("decoded");
+    recovered.apply(journaled.journal()).expect("applied");
+
+    let mut model: Vec<u64> = (0..20).collect();
+    model[3] = 42;
+    model.push(7);
+
+    assert_same(&recovered, &model);
+}
+
+#[test]
+fn invalid_journal() {
+    let mut journaled = Journaled::<u64, Cardinality>::new();
+    journaled.push(1);
+    journaled.pop();
+
+    let (_, journal) = journaled.into_inner();
+
+    // a pop without any element to pop
+    let mut bytes = journal.as_bytes().to_vec();
+    bytes.push(1);
+    assert_eq!(
+        NStack::<u64, Cardinality>::replay(&Journal::from_bytes(bytes)).err(),
+        Some(JournalError::InvalidRecord(2))
+    );
+
+    // a torn record at the end
+    let bytes = journal.as_bytes()[..5].to_vec();
+    assert_eq!(
+        NStack::<u64, Cardinality>::replay(&Journal::from_bytes(bytes)).err(),
+        Some(JournalError::UnexpectedEnd(0))
+    );
+}
@@ -5,10 +5,18 @@
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
 use core::borrow::Borrow;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::panic::{self, AssertUnwindSafe};
 
-use nstack::annotation::{Cardinality, Keyed, MaxKey};
+use microkelvin::{Branch, BranchMut};
+use nstack::annotation::{Bloom, Cardinality, Digest, KeyAbove, Keyed, MaxKey};
+use nstack::diff::DiffEntry;
+use nstack::find::Pruned;
+use nstack::stats::Level;
+use nstack::tombstone::Tombstoned;
+use nstack::validate::InvariantError;
 use nstack::NStack;
-use ranno::Annotation;
+use ranno::{Annotated, Annotation};
 
 #[test]
 fn trivial() {
@@ -106,7 +114,7 @@ fn branch_lengths() {
     assert_eq!(*max_branch, n - 1);
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 struct MaxAndCardinality<K> {
     cardinality: Cardinality,
     max_key: MaxKey<K>,
@@ -168,3 +176,1017 @@ impl<K> Borrow<MaxKey<K>> for MaxAndCardinality<K> {
         &self.max_key
     }
 }
+
+#[test]
+fn bloom() {
+    let n = 1024;
+
+    let mut nt = NStack::<u64, Bloom<u64>>::new();
+
+    for i in 0..n {
+        nt.push(i * 2);
+    }
+
+    for i in 0..n {
+        assert!(nt.contains_key(&(i * 2)));
+        assert_eq!(*nt.find_key(&(i * 2)).expect("Some(_)"), i * 2);
+        assert!(!nt.contains_key(&(i * 2 + 1)));
+    }
+
+    *nt.find_key_mut(&0).expect("Some(_)") = 1;
+
+    assert!(nt.contains_key(&1));
+    assert!(!nt.contains_key(&0));
+}
+
+#[test]
+fn find() {
+    let n = 1024;
+
+    let mut nt = NStack::<u64, MaxAndCardinality<u64>>::new();
+
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let above = |anno: &MaxAndCardinality<u64>| anno.max_key > 1000;
+
+    let found = nt.find(above, |leaf| *leaf > 1000).expect("Some(_)");
+    assert_eq!(*found, 1001);
+
+    assert!(nt.find(|_| true, |leaf| *leaf >= n).is_none());
+
+    *nt.find_mut(above, |leaf| *leaf == 1001).expect("Some(_)") = 0;
+
+    let all: Vec<_> = nt.find_all(above, |leaf| *leaf > 1000).collect();
+    assert_eq!(all.len(), (n - 1002) as usize);
+    assert!(all.iter().zip(1002..).all(|(leaf, i)| **leaf == i));
+
+    let evens = nt.find_all(|_| true, |leaf| leaf % 2 == 0).count();
+    assert_eq!(evens, 513);
+}
+
+#[test]
+fn filter_walk() {
+    let n = 1024;
+
+    let mut nt = NStack::<u64, MaxAndCardinality<u64>>::new();
+
+    for i in 0..n {
+        nt.push(i % 100);
+    }
+
+    let mut count = 0;
+
+    for (index, leaf) in nt.filter_walk(KeyAbove(95)) {
+        assert_eq!(*nt.nth(index).expect("Some(_)"), *leaf);
+        assert!(*leaf > 95);
+        count += 1;
+    }
+
+    assert_eq!(count, 4 * 10);
+    assert_eq!(nt.filter_walk(KeyAbove(99)).count(), 0);
+
+    // the filter also walks branches
+    let found = Branch::walk(&nt, Pruned::new(KeyAbove(95))).expect("Some(_)");
+    assert_eq!(*found, 96);
+    let mut found =
+        BranchMut::walk(&mut nt, Pruned::new(KeyAbove(98))).expect("Some(_)");
+    *found = 0;
+    drop(found);
+    assert_eq!(nt.filter_walk(KeyAbove(98)).count(), 10 - 1);
+    assert!(Branch::walk(&nt, Pruned::new(KeyAbove(99))).is_none());
+}
+
+#[test]
+fn cursor_mut() {
+    let n = 1024;
+
+    let mut nt = NStack::<u64, MaxAndCardinality<u64>>::new();
+
+    assert!(nt.cursor_mut().current().is_none());
+
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let mut cursor = nt.cursor_mut();
+    assert_eq!(cursor.len(), n);
+
+    *cursor.current_mut().expect("Some(_)") += 1;
+    while let Some(leaf) = cursor.next() {
+        *leaf += 1;
+    }
+    assert_eq!(cursor.position(), n - 1);
+
+    for i in (0..n - 1).rev() {
+        assert_eq!(*cursor.prev().expect("Some(_)"), i + 1);
+    }
+    assert!(cursor.prev().is_none());
+
+    *cursor.seek(517).expect("Some(_)") = 2 * n;
+    assert_eq!(cursor.current(), Some(&(2 * n)));
+    assert_eq!(cursor.next(), Some(&mut 519));
+    assert!(cursor.seek(n).is_none());
+    assert_eq!(cursor.position(), 518);
+
+    drop(cursor);
+
+    assert_eq!(*nt.max_key().expect("Some(_)"), 2 * n);
+    assert_eq!(*nt.nth(517).expect("Some(_)"), 2 * n);
+
+    for i in 0..n {
+        if i != 517 {
+            assert_eq!(*nt.nth(i).expect("Some(_)"), i + 1);
+        }
+    }
+
+    assert_eq!(
+        *Borrow::<Cardinality>::borrow(&MaxAndCardinality::from_child(&nt)),
+        n
+    );
+}
+
+#[test]
+fn diff() {
+    let mut old = NStack::<u64, Cardinality>::new();
+    let mut new = NStack::<u64, Cardinality>::new();
+
+    for i in 0..20 {
+        old.push(i);
+    }
+    for i in 0..70 {
+        new.push(if i == 3 || i == 17 { 100 + i } else { i });
+    }
+
+    let entries: Vec<_> = old.diff(&new).collect();
+
+    assert_eq!(
+        entries[0],
+        DiffEntry::Changed {
+            index: 3,
+            old: &3,
+            new: &103
+        }
+    );
+    assert_eq!(entries[1].index(), 17);
+    assert_eq!(entries.len(), 2 + 50);
+
+    for (entry, i) in entries[2..].iter().zip(20..) {
+        assert_eq!(*entry, DiffEntry::Appended { index: i, new: &i });
+    }
+
+    let entries: Vec<_> = new.diff(&old).collect();
+    assert_eq!(entries.len(), 2 + 50);
+    assert_eq!(
+        entries[51],
+        DiffEntry::Truncated {
+            index: 69,
+            old: &69
+        }
+    );
+
+    assert_eq!(old.diff(&old).count(), 0);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sum {
+    sum: u64,
+    cardinality: Cardinality,
+}
+
+impl Annotation<NStack<u64, Sum>> for Sum {
+    fn from_child(stack: &NStack<u64, Sum>) -> Self {
+        let (sum, cardinality) = match stack {
+            NStack::Leaf(leaf) => {
+                (leaf.iter().flatten().sum(), leaf.iter().flatten().count())
+            }
+            NStack::Node(node) => {
+                node.iter().flatten().fold((0, 0), |(sum, cardinality), a| {
+                    let anno = a.anno();
+                    (sum + anno.sum, cardinality + *anno.cardinality as usize)
+                })
+            }
+        };
+        let cardinality = (cardinality as u64).into();
+        Sum { sum, cardinality }
+    }
+}
+
+impl Borrow<Cardinality> for Sum {
+    fn borrow(&self) -> &Cardinality {
+        &self.cardinality
+    }
+}
+
+impl Digest for Sum {
+    fn digest_eq(&self, other: &Self) -> bool {
+        self.sum == other.sum
+    }
+}
+
+#[test]
+fn diff_skips_digest_equal() {
+    let mut old = NStack::<u64, Sum>::new();
+    let mut new = NStack::<u64, Sum>::new();
+
+    for i in 0..64 {
+        old.push(i);
+        new.push(match i {
+            20 => 21,
+            21 => 20,
+            i => i,
+        });
+    }
+
+    // the swapped elements are in a subtree with an equal digest
+    assert_eq!(old.diff(&new).count(), 0);
+
+    new.pop();
+    new.push(0);
+
+    let entries: Vec<_> = old.diff(&new).collect();
+    assert_eq!(
+        entries,
+        [DiffEntry::Changed {
+            index: 63,
+            old: &63,
+            new: &0
+        }]
+    );
+}
+
+#[test]
+fn diff_relaxed() {
+    let mut a = NStack::<u64, Cardinality>::new();
+    let mut b = NStack::<u64, Cardinality>::new();
+    for i in 0..20 {
+        a.push(i);
+    }
+    for i in 1..20 {
+        b.push(i);
+    }
+    b.push_front(0);
+
+    // the same elements, in differently shaped trees
+    assert!(a == b);
+    assert!(a.digest_eq(&b));
+    assert_eq!(a.diff(&b).count(), 0);
+
+    *b.nth_mut(10).expect("Some(_)") = 100;
+    b.insert(5, 200);
+    let old: Vec<u64> = a.iter().copied().collect();
+    let new: Vec<u64> = b.iter().copied().collect();
+
+    let entries: Vec<_> = a.diff(&b).collect();
+    let mut expected: Vec<_> = old
+        .iter()
+        .zip(&new)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(i, (old, new))| DiffEntry::Changed {
+            index: i as u64,
+            old,
+            new,
+        })
+        .collect();
+    expected.push(DiffEntry::Appended {
+        index: 20,
+        new: &new[20],
+    });
+    assert_eq!(entries, expected);
+    assert_eq!(entries[0].index(), 5);
+}
+
+#[test]
+fn truncate() {
+    let mut nt = NStack::<u32, Cardinality>::new();
+
+    for i in 0..100 {
+        nt.push(i);
+    }
+
+    nt.truncate(200);
+    assert_eq!(Cardinality::from_child(&nt), 100);
+
+    nt.truncate(37);
+    assert_eq!(Cardinality::from_child(&nt), 37);
+    assert_eq!(nt.pop(), Some(36));
+
+    nt.truncate(0);
+    assert_eq!(nt.pop(), None);
+}
+
+#[test]
+fn validate() {
+    let mut nt = NStack::<u32, Cardinality>::new();
+
+    for i in 0..300 {
+        nt.push(i);
+        assert_eq!(nt.validate(), Ok(()));
+    }
+    for _ in 0..300 {
+        nt.pop();
+        assert_eq!(nt.validate(), Ok(()));
+    }
+}
+
+#[test]
+fn rebuild() {
+    let leaf = |elements: &[u32]| {
+        let mut leaf = [None; 4];
+        for (slot, t) in leaf.iter_mut().zip(elements) {
+            *slot = Some(*t);
+        }
+        Annotated::new(Box::new(NStack::Leaf(leaf)))
+    };
+
+    let mut gap =
+        NStack::<u32, Cardinality>::Leaf([Some(0), None, Some(1), None]);
+    assert_eq!(
+        gap.validate(),
+        Err(InvariantError::NotLeftPacked { path: vec![] })
+    );
+
+    // under-full blocks are allowed
+    let mut not_full = NStack::<u32, Cardinality>::Node([
+        Some(leaf(&[0, 1, 2, 3])),
+        Some(leaf(&[4, 5])),
+        Some(leaf(&[6])),
+        None,
+    ]);
+    assert_eq!(not_full.validate(), Ok(()));
+
+    let mut empty = NStack::<u32, Cardinality>::Node([
+        Some(leaf(&[0, 1])),
+        Some(leaf(&[])),
+        None,
+        None,
+    ]);
+    assert_eq!(
+        empty.validate(),
+        Err(InvariantError::Empty { path: vec![1] })
+    );
+
+    let mut uneven = NStack::<u32, Cardinality>::Node([
+        Some(leaf(&[0, 1, 2, 3])),
+        Some(Annotated::new(Box::new(NStack::Node([
+            Some(leaf(&[4])),
+            None,
+            None,
+            None,
+        ])))),
+        None,
+        None,
+    ]);
+    assert_eq!(
+        uneven.validate(),
+        Err(InvariantError::UnevenDepth { path: vec![1] })
+    );
+
+    for (nt, n) in [
+        (&mut gap, 2),
+        (&mut not_full, 7),
+        (&mut empty, 2),
+        (&mut uneven, 5),
+    ] {
+        nt.rebuild();
+        assert_eq!(nt.validate(), Ok(()));
+        assert_eq!(Cardinality::from_child(nt), n);
+        for i in 0..n {
+            assert_eq!(*nt.nth(i).expect("Some(_)"), i as u32);
+        }
+    }
+}
+
+#[test]
+fn stats() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+
+    let stats = nt.stats();
+    assert_eq!(stats.height, 1);
+    assert_eq!((stats.leaves, stats.nodes), (1, 0));
+    assert_eq!(stats.empty_slots, 4);
+    assert_eq!(stats.heap_bytes, 0);
+
+    for i in 0..21 {
+        nt.push(i);
+    }
+
+    let stats = nt.stats();
+    assert_eq!(stats.height, 3);
+    assert_eq!((stats.leaves, stats.nodes), (6, 3));
+    assert_eq!(
+        stats.levels,
+        vec![
+            Level {
+                blocks: 1,
+                occupied: 2
+            },
+            Level {
+                blocks: 2,
+                occupied: 6
+            },
+            Level {
+                blocks: 6,
+                occupied: 21
+            },
+        ]
+    );
+    assert_eq!(stats.levels[2].slots(), 24);
+    assert_eq!(stats.empty_slots, 2 + 2 + 3);
+    assert_eq!(
+        stats.heap_bytes,
+        8 * core::mem::size_of::<NStack<u64, Cardinality>>()
+    );
+}
+
+#[test]
+fn render() {
+    let mut nt = NStack::<u32, MaxKey<u32>>::new();
+
+    for i in [3, 9, 1, 4, 7, 2] {
+        nt.push(i);
+    }
+
+    assert_eq!(
+        nt.to_tree(),
+        "Node Maximum(9)\n  \
+           Leaf Maximum(9) [3, 9, 1, 4]\n  \
+           Leaf Maximum(7) [7, 2, _, _]\n"
+    );
+
+    assert_eq!(
+        nt.to_dot(),
+        "digraph nstack {\n    \
+           node [shape=record];\n    \
+           b0 [label=\"{Maximum(9)|{<s0>|<s1>|<s2>|<s3>}}\"];\n    \
+           b1 [label=\"{Maximum(9)|{3|9|1|4}}\"];\n    \
+           b0:s0 -> b1;\n    \
+           b2 [label=\"{Maximum(7)|{7|2|_|_}}\"];\n    \
+           b0:s1 -> b2;\n\
+         }\n"
+    );
+}
+
+#[test]
+fn iter() {
+    let mut nt = NStack::<u32, ()>::new();
+    assert_eq!(nt.iter().next(), None);
+
+    for i in 0..100 {
+        nt.push(i);
+    }
+    for _ in 0..40 {
+        nt.pop();
+    }
+
+    assert!(nt.iter().copied().eq(0..60));
+    assert_eq!((&nt).into_iter().count(), 60);
+}
+
+#[test]
+fn sequence_semantics() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash<H: Hash>(h: &H) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        h.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let mut a = NStack::<u32, Cardinality>::new();
+    let mut b = NStack::<u32, MaxKey<u32>>::new();
+
+    for i in 0..50 {
+        a.push(i);
+    }
+    // a different shape, with the same elements
+    for i in 0..80 {
+        b.push(i);
+    }
+    for _ in 0..30 {
+        b.pop();
+    }
+
+    assert!(a == b);
+    assert_eq!(a.partial_cmp(&b), Some(core::cmp::Ordering::Equal));
+
+    let mut c = NStack::<u32, Cardinality>::new();
+    for i in 0..80 {
+        c.push(i);
+    }
+    for _ in 0..30 {
+        c.pop();
+    }
+    assert_eq!(a, c);
+    assert_eq!(hash(&a), hash(&c));
+    assert!(a.digest_eq(&c));
+
+    c.pop();
+    assert_ne!(a, c);
+    assert!(!a.digest_eq(&c));
+    assert!(c < a);
+
+    *c.nth_mut(10).expect("Some(_)") = 100;
+    assert!(c > a);
+    assert_eq!(a.cmp(&a), core::cmp::Ordering::Equal);
+}
+
+static FROM_CHILD_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Default)]
+struct Counted;
+
+impl Annotation<NStack<u64, Counted>> for Counted {
+    fn from_child(_: &NStack<u64, Counted>) -> Self {
+        FROM_CHILD_CALLS.fetch_add(1, Ordering::Relaxed);
+        Counted
+    }
+}
+
+#[test]
+fn for_each_mut() {
+    let n: u64 = 1000;
+    let mut stack = NStack::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..n {
+        stack.push(i);
+    }
+
+    stack.for_each_mut(|t| *t = n - *t);
+
+    for i in 0..n {
+        assert_eq!(*stack.nth(i).expect("Some(_)"), n - i);
+    }
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), n);
+
+    // the visit stops at the first error, with annotations still up to date
+    let result = stack.try_for_each_mut(|t| {
+        if *t == 500 {
+            return Err(*t);
+        }
+        *t += n;
+        Ok(())
+    });
+    assert_eq!(result, Err(500));
+    assert_eq!(*stack.nth(499).expect("Some(_)"), 2 * n - 499);
+    assert_eq!(*stack.nth(500).expect("Some(_)"), 500);
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 2 * n);
+
+    // every annotation is computed once
+    let mut stack = NStack::<u64, Counted>::new();
+    for i in 0..n {
+        stack.push(i);
+    }
+    let blocks = stack.stats().leaves + stack.stats().nodes - 1;
+
+    FROM_CHILD_CALLS.store(0, Ordering::Relaxed);
+    stack.for_each_mut(|t| *t += 1);
+    assert_eq!(FROM_CHILD_CALLS.load(Ordering::Relaxed), blocks);
+}
+
+#[test]
+fn retain() {
+    let n: u64 = 1000;
+    let mut stack = NStack::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..n {
+        stack.push(i);
+    }
+
+    stack.retain(|t| t % 3 == 0 && *t < 900);
+
+    let expected: Vec<u64> = (0..900).step_by(3).collect();
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), expected);
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 897);
+    assert_eq!(*stack.nth(100).expect("Some(_)"), 300);
+
+    let mut pushed = NStack::<u64, MaxAndCardinality<u64>>::new();
+    for t in expected {
+        pushed.push(t);
+    }
+    assert_eq!(stack.stats(), pushed.stats());
+
+    stack.retain(|_| false);
+    assert_eq!(stack.stats(), NStack::<u64, Cardinality>::new().stats());
+}
+
+#[test]
+fn drain() {
+    let n: u64 = 1000;
+    let mut stack = NStack::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..n {
+        stack.push(i);
+    }
+
+    let drained: Vec<u64> = stack.drain(10..990).collect();
+    assert_eq!(drained, (10..990).collect::<Vec<_>>());
+
+    let expected: Vec<u64> = (0..10).chain(990..n).collect();
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), expected);
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(stack.stats().height, 3);
+    assert_eq!(*stack.nth(10).expect("Some(_)"), 990);
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 999);
+
+    // dropping the iterator still removes the elements
+    drop(stack.drain(..=9));
+    assert_eq!(
+        stack.iter().copied().collect::<Vec<_>>(),
+        (990..n).collect::<Vec<_>>()
+    );
+
+    assert_eq!(stack.drain(..).count(), 10);
+    assert_eq!(stack.pop(), None);
+}
+
+#[test]
+fn drain_retain_unwind() {
+    let mut stack = NStack::<u64, Cardinality>::new();
+    for i in 0..10 {
+        stack.push(i);
+    }
+
+    let result =
+        panic::catch_unwind(AssertUnwindSafe(|| stack.drain(5..11).count()));
+    assert!(result.is_err());
+    assert_eq!(
+        stack.iter().copied().collect::<Vec<_>>(),
+        (0..10).collect::<Vec<_>>()
+    );
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        stack.drain(..=u64::MAX).count()
+    }));
+    assert!(result.is_err());
+    assert_eq!(
+        stack.iter().copied().collect::<Vec<_>>(),
+        (0..10).collect::<Vec<_>>()
+    );
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        stack.retain(|t| match t {
+            7 => panic!("retain"),
+            t => t % 2 == 0,
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(
+        stack.iter().copied().collect::<Vec<_>>(),
+        (0..10).collect::<Vec<_>>()
+    );
+    assert_eq!(stack.validate(), Ok(()));
+}
+
+#[test]
+fn swap_remove() {
+    let n: u64 = 100;
+    let mut stack = NStack::<u64, MaxAndCardinality<u64>>::new();
+    let mut model = Vec::new();
+    for i in 0..n {
+        stack.push(i);
+        model.push(i);
+    }
+
+    assert_eq!(stack.swap_remove(n), None);
+
+    for index in [99, 0, 42, 42, 10, 94] {
+        assert_eq!(
+            stack.swap_remove(index),
+            Some(model.swap_remove(index as usize))
+        );
+        assert_eq!(stack.iter().copied().collect::<Vec<_>>(), model);
+        assert_eq!(stack.validate(), Ok(()));
+    }
+    assert_eq!(stack.max_key::<u64>().as_deref(), model.iter().max());
+
+    while !model.is_empty() {
+        assert_eq!(stack.swap_remove(0), Some(model.swap_remove(0)));
+    }
+    assert_eq!(stack.swap_remove(0), None);
+}
+
+#[test]
+fn insert_remove() {
+    let mut stack = NStack::<u64, MaxAndCardinality<u64>>::new();
+    let mut model = Vec::new();
+
+    // a simple linear congruential generator for positions
+    let mut seed: u64 = 17;
+    let mut next = |bound: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (seed >> 33) as usize % bound
+    };
+
+    for i in 0..2000 {
+        let index = next(model.len() + 1);
+        stack.insert(index as u64, i);
+        model.insert(index, i);
+
+        if i % 7 == 0 {
+            stack.push(i);
+            model.push(i);
+        }
+    }
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), model);
+
+    // the height stays logarithmic
+    assert!(stack.stats().height <= 8);
+
+    while model.len() > 10 {
+        let index = next(model.len());
+        assert_eq!(stack.remove(index as u64), model.remove(index));
+
+        if model.len() % 5 == 0 {
+            assert_eq!(stack.pop(), model.pop());
+            assert_eq!(stack.validate(), Ok(()));
+        }
+    }
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), model);
+
+    for (i, t) in model.iter().enumerate() {
+        assert_eq!(stack.nth(i as u64).as_deref(), Some(t));
+    }
+    assert_eq!(stack.max_key::<u64>().as_deref(), model.iter().max());
+
+    while !model.is_empty() {
+        assert_eq!(stack.remove(0), model.remove(0));
+    }
+    assert_eq!(stack.pop(), None);
+}
+
+#[test]
+#[should_panic]
+fn insert_out_of_bounds() {
+    let mut stack = NStack::<u64, Cardinality>::new();
+    stack.push(0);
+    stack.insert(2, 1);
+}
+
+#[test]
+#[should_panic]
+fn remove_out_of_bounds() {
+    let mut stack = NStack::<u64, Cardinality>::new();
+    stack.push(0);
+    stack.remove(1);
+}
+
+#[test]
+fn remove_hole() {
+    let n: u64 = 100;
+    let mut stack = Tombstoned::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..n {
+        stack.push(i);
+    }
+
+    // a whole leaf, and scattered elements
+    let holes = [4, 5, 6, 7, 10, 33, 64, 97, 98];
+    for &i in &holes {
+        assert_eq!(stack.remove_hole(i), Some(i));
+    }
+    assert_eq!(stack.remove_hole(10), None);
+    assert_eq!(stack.remove_hole(n), None);
+
+    let live: Vec<u64> = (0..n).filter(|i| !holes.contains(i)).collect();
+
+    assert_eq!(stack.positional_len(), n);
+    assert_eq!(stack.len(), live.len() as u64);
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), live);
+
+    for i in 0..n {
+        let expected = if holes.contains(&i) { None } else { Some(&i) };
+        assert_eq!(stack.nth_position(i).as_deref(), expected);
+    }
+    for (rank, i) in live.iter().enumerate() {
+        assert_eq!(stack.nth(rank as u64).as_deref(), Some(i));
+    }
+    assert!(stack.nth(live.len() as u64).is_none());
+
+    *stack.nth_position_mut(99).expect("Some(_)") = 1000;
+    *stack.nth_mut(0).expect("Some(_)") = 500;
+
+    // popping drops the holes before the last element
+    assert_eq!(stack.pop(), Some(1000));
+    assert_eq!(stack.positional_len(), 97);
+
+    // pushing appends after the last position
+    stack.push(200);
+    assert_eq!(stack.positional_len(), 98);
+    assert_eq!(stack.nth_position(97).as_deref(), Some(&200));
+
+    // the inner stack is handed out without holes
+    let mut expected: Vec<u64> = live[..live.len() - 1].to_vec();
+    expected[0] = 500;
+    expected.push(200);
+    let inner = stack.into_inner();
+    assert_eq!(inner.validate(), Ok(()));
+    assert_eq!(inner.iter().copied().collect::<Vec<_>>(), expected);
+    assert_eq!(*inner.max_key::<u64>().expect("Some(_)"), 500);
+
+    // popping through a leaf of holes
+    let mut stack = Tombstoned::<u64, Cardinality>::new();
+    for i in 0..9 {
+        stack.push(i);
+    }
+    for i in 4..8 {
+        stack.remove_hole(i);
+    }
+    assert_eq!(stack.pop(), Some(8));
+    assert_eq!(stack.positional_len(), 4);
+    stack.push(4);
+    assert_eq!(stack.nth_position(4).as_deref(), Some(&4));
+
+    // down to an empty stack
+    for i in (0..5).rev() {
+        assert_eq!(stack.remove_hole(i), Some(i));
+    }
+    assert!(stack.is_empty());
+    assert_eq!(stack.positional_len(), 0);
+    assert_eq!(stack.pop(), None);
+    for i in 0..5 {
+        stack.push(i);
+    }
+
+    stack.remove_hole(1);
+    stack.compact();
+    assert_eq!(stack.positional_len(), 4);
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), [0, 2, 3, 4]);
+
+    let inner = stack.into_inner();
+    assert_eq!(inner.validate(), Ok(()));
+    assert_eq!(inner.stats(), {
+        let mut packed = NStack::<u64, Cardinality>::new();
+        for i in [0, 2, 3, 4] {
+            packed.push(i);
+        }
+        packed.stats()
+    });
+}
+
+#[test]
+fn remove_hole_insert_remove() {
+    let mut stack = Tombstoned::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..4 {
+        stack.push(i);
+    }
+    stack.remove_hole(1);
+    let mut stack = stack.into_inner();
+    stack.insert(1, 99);
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), [0, 99, 2, 3]);
+
+    let mut stack = Tombstoned::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..20 {
+        stack.push(i);
+    }
+    stack.remove_hole(5);
+    let mut stack = stack.into_inner();
+    assert_eq!(stack.remove(5), 6);
+
+    let model: Vec<u64> = (0..20).filter(|&i| i != 5 && i != 6).collect();
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), model);
+
+    // holes in leaves that are split, merged and balanced
+    let mut stack = Tombstoned::<u64, MaxAndCardinality<u64>>::new();
+    for i in 0..200 {
+        stack.push(i);
+    }
+    for i in (1..200).step_by(3) {
+        stack.remove_hole(i);
+    }
+    let mut stack = stack.into_inner();
+    let mut model: Vec<u64> = (0..200).filter(|i| i % 3 != 1).collect();
+
+    for i in 0..100 {
+        let index = (i * 7) % model.len();
+        stack.insert(index as u64, 1000 + i as u64);
+        model.insert(index, 1000 + i as u64);
+
+        let index = (i * 13) % model.len();
+        assert_eq!(stack.remove(index as u64), model.remove(index));
+        let index = (i * 5) % model.len();
+        assert_eq!(stack.remove(index as u64), model.remove(index));
+    }
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), model);
+    for (i, t) in model.iter().enumerate() {
+        assert_eq!(stack.nth(i as u64).as_deref(), Some(t));
+    }
+
+    while !model.is_empty() {
+        assert_eq!(stack.remove(0), model.remove(0));
+    }
+    assert_eq!(stack.pop(), None);
+}
+
+#[test]
+fn tombstoned_from_relaxed() {
+    // a stack relaxed by pushing to the front is packed again
+    let mut stack = NStack::<u64, Cardinality>::new();
+    for i in 1..8 {
+        stack.push(i);
+    }
+    stack.push_front(0);
+    let mut stack = Tombstoned::from(stack);
+    assert_eq!(stack.positional_len(), 8);
+    assert_eq!(stack.remove_hole(5), Some(5));
+    assert_eq!(stack.nth_position(6).as_deref(), Some(&6));
+
+    let mut stack = NStack::<u64, Cardinality>::new();
+    for i in 0..20 {
+        stack.push(i);
+    }
+    stack.pop_front();
+    let stack = Tombstoned::from(stack);
+    assert_eq!(stack.positional_len(), 19);
+    for i in 0..19 {
+        assert_eq!(stack.nth_position(i).as_deref(), Some(&(i + 1)));
+    }
+
+    // holes of a previously tombstoned stack are squeezed out
+    let mut stack = Tombstoned::<u64, Cardinality>::new();
+    for i in 0..8 {
+        stack.push(i);
+    }
+    stack.remove_hole(2);
+    let stack = Tombstoned::from(stack.into_inner());
+    assert_eq!(stack.positional_len(), 7);
+    assert_eq!(stack.nth_position(2).as_deref(), Some(&3));
+}
+
+#[test]
+fn queue() {
+    let mut stack = NStack::<u64, MaxAndCardinality<u64>>::new();
+    let mut model = std::collections::VecDeque::new();
+
+    assert_eq!(stack.pop_front(), None);
+
+    for i in 0..10_000 {
+        stack.push(i);
+        model.push_back(i);
+
+        if i % 3 == 0 {
+            assert_eq!(stack.pop_front(), model.pop_front());
+        }
+        if i % 100 == 0 {
+            stack.push_front(i);
+            model.push_front(i);
+        }
+    }
+
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(
+        stack.iter().collect::<Vec<_>>(),
+        model.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(stack.nth(1000).as_deref(), model.get(1000));
+    assert_eq!(stack.max_key::<u64>().as_deref(), model.iter().max());
+
+    // blocks emptied at the front are reclaimed
+    while model.len() > 10 {
+        assert_eq!(stack.pop_front(), model.pop_front());
+    }
+    let stats = stack.stats();
+    assert!(stats.leaves + stats.nodes <= 6);
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(stack.max_key::<u64>().as_deref(), model.iter().max());
+
+    while let Some(t) = model.pop_front() {
+        assert_eq!(stack.pop_front(), Some(t));
+    }
+    assert_eq!(stack.pop_front(), None);
+    assert_eq!(stack.pop(), None);
+}
+
+#[test]
+fn queue_holes() {
+    let holey = |holes: &[u64]| {
+        let mut stack = Tombstoned::<u64, Cardinality>::new();
+        for i in 0..20 {
+            stack.push(i);
+        }
+        for &i in holes {
+            stack.remove_hole(i);
+        }
+        stack.into_inner()
+    };
+
+    let mut stack = holey(&[0]);
+    assert_eq!(stack.pop_front(), Some(1));
+    assert_eq!(stack.validate(), Ok(()));
+
+    let mut stack = holey(&[0, 1, 5, 6, 7, 8]);
+    stack.push_front(100);
+    stack.push_front(101);
+    let mut model = vec![101, 100, 2, 3, 4];
+    model.extend(9..20);
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(stack.iter().copied().collect::<Vec<_>>(), model);
+
+    for t in model {
+        assert_eq!(stack.pop_front(), Some(t));
+    }
+    assert_eq!(stack.pop_front(), None);
+}
@@ -0,0 +1,102 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+#![cfg(feature = "rayon")]
+
+use nstack::annotation::{Cardinality, MaxKey};
+use nstack::NStack;
+
+#[test]
+fn par_from_vec() {
+    for n in [0, 1, 4, 5, 16, 17, 64, 65, 1000, 10_000] {
+        let stack = NStack::<u64, Cardinality>::par_from_vec((0..n).collect());
+
+        let mut pushed = NStack::<u64, Cardinality>::new();
+        for i in 0..n {
+            pushed.push(i);
+        }
+
+        assert_eq!(stack.validate(), Ok(()));
+        assert_eq!(stack.stats(), pushed.stats());
+        assert_eq!(stack, pushed);
+
+        for i in 0..n {
+            assert_eq!(*stack.nth(i).expect("Some(_)"), i);
+        }
+    }
+
+    let stack =
+        NStack::<u64, MaxKey<u64>>::par_from_vec((0..1000).rev().collect());
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 999);
+}
+
+#[test]
+fn par_iter() {
+    use rayon::prelude::*;
+
+    for n in [0, 1, 5, 17, 1000] {
+        let mut stack =
+            NStack::<u64, Cardinality>::par_from_vec((0..n).collect());
+
+        let sum: u64 = stack.par_iter().sum();
+        assert_eq!(sum, (0..n).sum::<u64>());
+
+        let collected: Vec<u64> = stack.par_iter().copied().collect();
+        assert_eq!(collected, (0..n).collect::<Vec<_>>());
+
+        stack.par_for_each_mut(|t| *t *= 2);
+        for i in 0..n {
+            assert_eq!(*stack.nth(i).expect("Some(_)"), i * 2);
+        }
+    }
+
+    let stack = NStack::<u64, MaxKey<u64>>::par_from_vec((0..1000).collect());
+    assert_eq!((&stack).into_par_iter().max(), Some(&999));
+}
+
+#[test]
+fn par_for_each_mut() {
+    let mut stack = NStack::<u64, MaxKey<u64>>::new();
+    for i in 0..10_000 {
+        stack.push(i);
+    }
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 9999);
+
+    stack.par_for_each_mut(|t| *t = 10_000 - *t);
+
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 10_000);
+    assert_eq!(stack.iter().next(), Some(&10_000));
+}
+
+#[test]
+fn par_iter_mut() {
+    use rayon::prelude::*;
+
+    let mut stack =
+        NStack::<u64, MaxKey<u64>>::par_from_vec((0..10_000).collect());
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 9999);
+
+    stack.par_iter_mut().for_each(|t| *t = 10_000 - *t);
+
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 10_000);
+    assert!(stack.iter().copied().eq((1..=10_000).rev()));
+
+    // the references outlive the walk
+    let mut evens: Vec<&mut u64> = (&mut stack)
+        .into_par_iter()
+        .filter(|t| **t % 2 == 0)
+        .collect();
+    assert_eq!(evens.len(), 5000);
+    for t in evens.iter_mut() {
+        **t = 0;
+    }
+
+    assert_eq!(stack.validate(), Ok(()));
+    assert_eq!(*stack.max_key::<u64>().expect("Some(_)"), 9999);
+    assert_eq!(stack.par_iter().filter(|t| **t == 0).count(), 5000);
+}
@@ -0,0 +1,203 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use nstack::annotation::{Cardinality, MaxKey};
+use nstack::block::Blocks;
+use nstack::diff::DiffEntry;
+use nstack::persistent::PersistentNStack;
+use ranno::Annotation;
+
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+#[test]
+fn push_pop() {
+    let n = 1024;
+
+    let mut nt = PersistentNStack::<u32, Cardinality>::new();
+
+    for i in 0..n {
+        nt.push(i);
+        assert_eq!(Cardinality::from_child(&nt), (i + 1) as u64);
+    }
+
+    for i in 0..n {
+        assert_eq!(*nt.nth(i as u64).expect("Some(_)"), i);
+    }
+
+    for i in 0..n {
+        assert_eq!(nt.pop(), Some(n - i - 1));
+    }
+
+    assert_eq!(nt.pop(), None);
+}
+
+#[test]
+fn clone_shares_structure() {
+    let n = 1024;
+
+    let mut nt = PersistentNStack::<u64, Cardinality>::new();
+
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let snapshot = nt.clone();
+    assert!(nt.ptr_eq(&snapshot));
+
+    *nt.nth_mut(n - 1).expect("Some(_)") = 0;
+    assert_eq!(nt.pop(), Some(0));
+    nt.push(n);
+
+    assert!(!nt.ptr_eq(&snapshot));
+
+    // only the path to the last element was copied
+    match (&nt, &snapshot) {
+        (PersistentNStack::Node(a), PersistentNStack::Node(b)) => {
+            let a = a[0].as_ref().expect("Some(_)").child();
+            let b = b[0].as_ref().expect("Some(_)").child();
+            assert!(a.ptr_eq(b));
+        }
+        _ => panic!("root should be a node"),
+    }
+
+    for i in 0..n {
+        assert_eq!(*snapshot.nth(i).expect("Some(_)"), i);
+    }
+    assert_eq!(Cardinality::from_child(&snapshot), n);
+    assert_eq!(Cardinality::from_child(&nt), n);
+    assert_eq!(*nt.nth(n - 1).expect("Some(_)"), n);
+}
+
+#[test]
+fn max_key() {
+    let n = 1024;
+
+    let mut nt = PersistentNStack::<u64, MaxKey<u64>>::new();
+
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let snapshot = nt.clone();
+
+    *nt.max_key_mut().expect("Some(_)") = 0;
+
+    assert_eq!(*nt.max_key().expect("Some(_)"), n - 2);
+    assert_eq!(*snapshot.max_key().expect("Some(_)"), n - 1);
+}
+
+#[test]
+fn diff() {
+    let n = 1024;
+
+    let mut nt = PersistentNStack::<u64, Cardinality>::new();
+
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let mut changed = nt.clone();
+    *changed.nth_mut(500).expect("Some(_)") = 0;
+    changed.push(n);
+
+    let entries: Vec<_> = nt.diff(&changed).collect();
+    assert_eq!(
+        entries,
+        [
+            DiffEntry::Changed {
+                index: 500,
+                old: &500,
+                new: &0
+            },
+            DiffEntry::Appended { index: n, new: &n }
+        ]
+    );
+}
+
+thread_local! {
+    static COMPARED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// An element counting how many times it is compared
+#[derive(Debug, Clone)]
+struct Counted(u64);
+
+impl PartialEq for Counted {
+    fn eq(&self, other: &Self) -> bool {
+        COMPARED.with(|compared| compared.set(compared.get() + 1));
+        self.0 == other.0
+    }
+}
+
+#[test]
+fn diff_skips_shared() {
+    let n = 1024;
+
+    // the annotations can't tell equal subtrees apart
+    let mut nt = PersistentNStack::<Counted, Cardinality>::new();
+
+    for i in 0..n {
+        nt.push(Counted(i));
+    }
+
+    let mut changed = nt.clone();
+    changed.nth_mut(500).expect("Some(_)").0 = 0;
+
+    // so only sharing lets the diff skip all but the leaf of the change
+    COMPARED.with(|compared| compared.set(0));
+    assert_eq!(nt.diff(&changed).count(), 1);
+    assert!(COMPARED.with(Cell::get) <= 4);
+}
+
+#[test]
+fn sequence_semantics() {
+    let mut a = PersistentNStack::<u64, Cardinality>::new();
+
+    for i in 0..200 {
+        a.push(i);
+    }
+
+    let mut b = a.clone();
+    assert_eq!(a, b);
+
+    *b.nth_mut(150).expect("Some(_)") = 0;
+    assert_ne!(a, b);
+    assert!(b < a);
+
+    *b.nth_mut(150).expect("Some(_)") = 150;
+    assert_eq!(a, b);
+    assert!(a.iter().copied().eq(0..200));
+}
+
+#[test]
+fn sequence_semantics_without_cardinality() {
+    fn hash<T: Hash>(t: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // annotations that can neither index nor digest
+    let mut a = PersistentNStack::<u64, MaxKey<u64>>::new();
+    let mut b = PersistentNStack::<u64, MaxKey<u64>>::new();
+
+    for i in 0..100 {
+        a.push(i);
+        b.push(i);
+    }
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+
+    b.pop();
+    assert_ne!(a, b);
+    assert!(b < a);
+    assert_ne!(hash(&a), hash(&b));
+
+    b.push(100);
+    assert!(a < b);
+}
@@ -0,0 +1,255 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+#![cfg(feature = "serde")]
+
+use nstack::annotation::{Cardinality, MaxKey};
+use nstack::tombstone::Tombstoned;
+use nstack::NStack;
+use ranno::Annotation;
+
+#[test]
+fn round_trip() {
+    for n in [0, 1, 4, 5, 17, 64, 65, 1000] {
+        let mut nt = NStack::<u64, Cardinality>::new();
+
+        for i in 0..n {
+            nt.push(i);
+        }
+
+        let json = serde_json::to_string(&nt).expect("serialized");
+        let back: NStack<u64, Cardinality> =
+            serde_json::from_str(&json).expect("deserialized");
+
+        assert_eq!(Cardinality::from_child(&back), n);
+        for i in 0..n {
+            assert_eq!(*back.nth(i).expect("Some(_)"), i);
+        }
+
+        // the shape is preserved
+        assert_eq!(serde_json::to_string(&back).expect("serialized"), json);
+    }
+}
+
+#[test]
+fn round_trip_after_pop() {
+    let mut nt = NStack::<u64, MaxKey<u64>>::new();
+
+    for i in 0..100 {
+        nt.push(i);
+    }
+    for _ in 0..100 {
+        nt.pop();
+    }
+
+    let json = serde_json::to_string(&nt).expect("serialized");
+    let mut back: NStack<u64, MaxKey<u64>> =
+        serde_json::from_str(&json).expect("deserialized");
+
+    assert_eq!(serde_json::to_string(&back).expect("serialized"), json);
+
+    back.push(3);
+    back.push(7);
+    assert_eq!(*back.max_key::<u64>().expect("Some(_)"), 7);
+}
+
+#[test]
+fn round_trip_after_insert() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+
+    for i in 0..50 {
+        nt.insert(i / 2, i);
+    }
+
+    let json = serde_json::to_string(&nt).expect("serialized");
+    let back: NStack<u64, Cardinality> =
+        serde_json::from_str(&json).expect("deserialized");
+
+    assert_eq!(back, nt);
+    assert_eq!(serde_json::to_string(&back).expect("serialized"), json);
+
+    // under-full children other than the last
+    let json = r#"{"Node":[[3,{"Leaf":[0,1,2]}],[1,{"Leaf":[4]}]]}"#;
+    let nt = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect("deserialized");
+    assert_eq!(*nt.nth(3).expect("Some(_)"), 4);
+}
+
+#[test]
+fn round_trip_edits() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+    let mut model = Vec::new();
+
+    // a simple linear congruential generator for edits and positions
+    let mut seed: u64 = 29;
+    let mut next = |bound: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (seed >> 33) as usize % bound
+    };
+
+    for i in 0..3000 {
+        match next(6) {
+            0 | 1 => {
+                nt.push(i);
+                model.push(i);
+            }
+            2 => {
+                assert_eq!(nt.pop(), model.pop());
+            }
+            3 => {
+                let index = next(model.len() + 1);
+                nt.insert(index as u64, i);
+                model.insert(index, i);
+            }
+            _ if !model.is_empty() => {
+                let index = next(model.len());
+                assert_eq!(nt.remove(index as u64), model.remove(index));
+            }
+            _ => (),
+        }
+
+        let json = serde_json::to_string(&nt).expect("serialized");
+        let back: NStack<u64, Cardinality> =
+            serde_json::from_str(&json).expect("deserialized");
+        assert_eq!(back.iter().copied().collect::<Vec<_>>(), model);
+    }
+}
+
+#[test]
+fn annotation_mismatch() {
+    let json = r#"{"Node":[[5,{"Leaf":[0,1,2,3]}],[1,{"Leaf":[4]}]]}"#;
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("annotation of child 0"));
+}
+
+#[test]
+fn illegal_shape() {
+    let json = r#"{"Leaf":[0,1,2,3,4]}"#;
+    assert!(serde_json::from_str::<NStack<u64, Cardinality>>(json).is_err());
+
+    // children of different heights
+    let json =
+        r#"{"Node":[[4,{"Leaf":[0,1,2,3]}],[1,{"Node":[[1,{"Leaf":[4]}]]}]]}"#;
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("height"));
+
+    // an empty child other than the first
+    let json = r#"{"Node":[[4,{"Leaf":[0,1,2,3]}],[0,{"Leaf":[]}]]}"#;
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("empty"));
+
+    let json = r#"{"Node":[]}"#;
+    assert!(serde_json::from_str::<NStack<u64, Cardinality>>(json).is_err());
+
+    // holes and trailing empty slots
+    let json = r#"{"Leaf":[0,null,2]}"#;
+    assert!(serde_json::from_str::<NStack<u64, Cardinality>>(json).is_err());
+    let json = r#"{"Leaf":[0,1,null,null]}"#;
+    assert!(serde_json::from_str::<NStack<u64, Cardinality>>(json).is_err());
+
+    // under-full children other than the last
+    let json = concat!(
+        r#"{"Node":[[1,{"Leaf":[0]}],[4,{"Leaf":[1,2,3,4]}],"#,
+        r#"[1,{"Leaf":[5]}]]}"#
+    );
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("child 0 holds 1 entries"));
+
+    // and their last children
+    let json = concat!(
+        r#"{"Node":[[5,{"Node":[[4,{"Leaf":[0,1,2,3]}],[1,{"Leaf":[4]}]]}],"#,
+        r#"[1,{"Node":[[1,{"Leaf":[5]}]]}]]}"#
+    );
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("child 1 holds 1 entries"));
+
+    // roots taller than needed
+    let json = r#"{"Node":[[2,{"Leaf":[0,1]}]]}"#;
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("single child"));
+}
+
+#[test]
+fn popped_root_is_skipped() {
+    let mut nt = NStack::<u64, Cardinality>::new();
+
+    for i in 0..5 {
+        nt.push(i);
+    }
+    nt.pop();
+
+    let json = serde_json::to_string(&nt).expect("serialized");
+    assert_eq!(json, r#"{"Leaf":[0,1,2,3]}"#);
+
+    for _ in 0..4 {
+        nt.pop();
+    }
+    let json = serde_json::to_string(&nt).expect("serialized");
+    assert_eq!(json, r#"{"Leaf":[]}"#);
+}
+
+#[test]
+fn round_trip_holes() {
+    let mut nt = Tombstoned::<u64, Cardinality>::new();
+
+    for i in 0..20 {
+        nt.push(i);
+    }
+    for i in [1, 4, 5, 6, 7, 18] {
+        nt.remove_hole(i);
+    }
+
+    let json = serde_json::to_string(&nt).expect("serialized");
+    assert!(json.contains(r#"{"Leaf":[0,null,2,3]}"#));
+    assert!(json.contains(r#"{"Leaf":[null,null,null,null]}"#));
+
+    let back: Tombstoned<u64, Cardinality> =
+        serde_json::from_str(&json).expect("deserialized");
+
+    // the holes are kept in place
+    assert_eq!(back.positional_len(), 20);
+    for i in 0..20 {
+        assert_eq!(
+            back.nth_position(i).as_deref(),
+            nt.nth_position(i).as_deref()
+        );
+    }
+    assert_eq!(serde_json::to_string(&back).expect("serialized"), json);
+
+    // but a plain stack has none
+    let err = serde_json::from_str::<NStack<u64, Cardinality>>(&json)
+        .expect_err("Err(_)");
+    assert!(err.to_string().contains("null"));
+    let json = serde_json::to_string(&nt.into_inner()).expect("serialized");
+    assert!(json.contains(r#"{"Leaf":[0,2,3,8]}"#));
+
+    // leaves of a tombstoned stack hold all their slots, and the stack is
+    // packed
+    let parse = serde_json::from_str::<Tombstoned<u64, Cardinality>>;
+    assert!(parse(r#"{"Leaf":[0,null]}"#).is_err());
+    assert!(parse(concat!(
+        r#"{"Node":[[4,{"Node":[[4,{"Leaf":[0,1,2,3]}]]}],"#,
+        r#"[1,{"Node":[[1,{"Leaf":[4,null,null,null]}]]}]]}"#
+    ))
+    .is_err());
+    assert!(parse(concat!(
+        r#"{"Node":[[4,{"Leaf":[0,1,2,3]}],"#,
+        r#"[0,{"Leaf":[null,null,null,null]}]]}"#
+    ))
+    .is_err());
+    assert!(parse(r#"{"Node":[[4,{"Leaf":[0,1,2,3]}]]}"#).is_err());
+    assert!(parse(concat!(
+        r#"{"Node":[[4,{"Leaf":[0,1,2,3]}],"#,
+        r#"[1,{"Leaf":[null,4,null,null]}]]}"#
+    ))
+    .is_ok());
+}
@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+#![cfg(feature = "store")]
+
+use nstack::annotation::{Bloom, Cardinality, MaxKey};
+use nstack::block::Blocks;
+use nstack::store::{DirStore, Id, Store, StoredNStack};
+use nstack::NStack;
+use ranno::Annotation;
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "nstack-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
+
+/// A store counting the blocks read from it
+struct Counting {
+    inner: DirStore,
+    reads: AtomicUsize,
+}
+
+impl Store for Counting {
+    fn put(&self, bytes: &[u8]) -> io::Result<Id> {
+        self.inner.put(bytes)
+    }
+
+    fn get(&self, id: Id) -> io::Result<Vec<u8>> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.inner.get(id)
+    }
+}
+
+#[test]
+fn persist_and_open() {
+    let n = 1000;
+    let path = temp_dir("persist-and-open");
+
+    let mut nt = NStack::<u64, Cardinality>::new();
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let store = DirStore::new(&path).expect("store");
+    let root = nt.persist(&store).expect("persisted");
+    drop(store);
+
+    // reopening the directory keeps the blocks
+    let store: Arc<dyn Store> = Arc::new(DirStore::new(&path).expect("store"));
+    let stored =
+        StoredNStack::<u64, Cardinality>::open(store, root).expect("opened");
+
+    assert_eq!(Cardinality::from_child(&stored), n);
+    for i in 0..n {
+        assert_eq!(*stored.nth(i).expect("Some(_)"), i);
+    }
+    assert!(stored.nth(n).is_none());
+
+    assert!(stored.iter().copied().eq(0..n));
+
+    std::fs::remove_dir_all(path).expect("removed");
+}
+
+#[test]
+fn loads_lazily() {
+    let n = 4u64.pow(5);
+    let path = temp_dir("loads-lazily");
+
+    let mut nt = NStack::<u64, MaxKey<u64>>::new();
+    for i in 0..n {
+        nt.push((i * 7919) % n);
+    }
+
+    let store = Arc::new(Counting {
+        inner: DirStore::new(&path).expect("store"),
+        reads: AtomicUsize::new(0),
+    });
+    let root = nt.persist(&*store).expect("persisted");
+
+    let stored = StoredNStack::<u64, MaxKey<u64>>::open(store.clone(), root)
+        .expect("opened");
+    assert_eq!(store.reads.load(Ordering::Relaxed), 1);
+
+    // the root annotation is known without loading any other block
+    assert_eq!(MaxKey::from_child(&stored), MaxKey::Maximum(n - 1));
+    assert_eq!(store.reads.load(Ordering::Relaxed), 1);
+
+    // finding the largest key only loads the blocks along its path
+    assert_eq!(*stored.max_key::<u64>().expect("Some(_)"), n - 1);
+    assert_eq!(store.reads.load(Ordering::Relaxed), 5);
+
+    // every block is only loaded once
+    assert_eq!(stored.iter().count() as u64, n);
+    let blocks = (0..5).map(|h| 4usize.pow(h)).sum::<usize>();
+    assert_eq!(store.reads.load(Ordering::Relaxed), blocks);
+
+    std::fs::remove_dir_all(path).expect("removed");
+}
+
+#[test]
+fn unload() {
+    let n = 4u64.pow(3);
+    let path = temp_dir("unload");
+
+    let mut nt = NStack::<u64, Cardinality>::new();
+    for i in 0..n {
+        nt.push(i);
+    }
+
+    let store = Arc::new(Counting {
+        inner: DirStore::new(&path).expect("store"),
+        reads: AtomicUsize::new(0),
+    });
+    let root = nt.persist(&*store).expect("persisted");
+
+    let mut stored =
+        StoredNStack::<u64, Cardinality>::open(store.clone(), root)
+            .expect("opened");
+    let blocks = 1 + 4 + 16;
+
+    assert!(stored.iter().copied().eq(0..n));
+    assert_eq!(store.reads.load(Ordering::Relaxed), blocks);
+
+    // unloaded blocks are loaded again when accessed
+    stored.unload();
+    assert!(stored.iter().copied().eq(0..n));
+    assert_eq!(store.reads.load(Ordering::Relaxed), 2 * blocks - 1);
+
+    // but edited blocks are kept
+    *stored.nth_mut(0).expect("Some(_)") = n;
+    stored.unload();
+    assert_eq!(*stored.nth(0).expect("Some(_)"), n);
+    assert_eq!(Cardinality::from_child(&stored), n);
+
+    std::fs::remove_dir_all(path).expect("removed");
+}
+
+#[test]
+fn try_nth() {
+    let path = temp_dir("try-nth");
+
+    let mut nt = NStack::<u64, Cardinality>::new();
+    for i in 0..20 {
+        nt.push(i);
+    }
+
+    let store = DirStore::new(&path).expect("store");
+    let root = nt.persist(&store).expect("persisted");
+
+    // the first leaf is the first block written
+    std::fs::remove_file(path.join(format!("{:016x}", 0))).expect("removed");
+
+    let store: Arc<dyn Store> = Arc::new(store);
+    let stored =
+        StoredNStack::<u64, Cardinality>::open(store, root).expect("opened");
+
+    assert!(stored.try_nth(0).is_err());
+    assert_eq!(*stored.try_nth(19).expect("Ok(_)").expect("Some(_)"), 19);
+    assert!(stored.try_nth(20).expect("Ok(_)").is_none());
+
+    std::fs::remove_dir_all(path).expect("removed");
+}
+
+#[test]
+fn bloom() {
+    let path = temp_dir("bloom");
+
+    let mut nt = NStack::<u64, Bloom<u64>>::new();
+    for i in 0..100 {
+        nt.push(i * 3);
+    }
+
+    let store = DirStore::new(&path).expect("store");
+    let root = nt.persist(&store).expect("persisted");
+
+    let store: Arc<dyn Store> = Arc::new(store);
+    let stored =
+        StoredNStack::<u64, Bloom<u64>>::open(store, root).expect("opened");
+
+    assert_eq!(Bloom::from_child(&stored), Bloom::from_child(&nt));
+    for i in 0..300 {
+        assert_eq!(stored.contains_key(&i), i % 3 == 0);
+    }
+
+    std::fs::remove_dir_all(path).expect("removed");
+}
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn arbitrary() {
+    use arbitrary::{Arbitrary, Unstructured};
+    use nstack::annotation::Cardinality;
+    use nstack::testing::History;
+    use nstack::NStack;
+    use ranno::Annotation;
+
+    let bytes: Vec<u8> = (0..1024u32).map(|i| (i * 7919 % 251) as u8).collect();
+
+    let history = History::<u16>::arbitrary(&mut Unstructured::new(&bytes))
+        .expect("history");
+    let stack: NStack<u16, Cardinality> = history.build();
+    let model = history.model();
+
+    assert_eq!(Cardinality::from_child(&stack), model.len() as u64);
+    assert!(stack.iter().eq(model.iter()));
+    assert_eq!(stack.validate(), Ok(()));
+
+    let stack = NStack::<u16, Cardinality>::arbitrary_take_rest(
+        Unstructured::new(&bytes),
+    )
+    .expect("stack");
+    assert_eq!(stack.validate(), Ok(()));
+}
+
+#[cfg(feature = "proptest")]
+mod proptest_strategies {
+    use nstack::annotation::{Cardinality, MaxKey};
+    use nstack::testing::strategy::nstack;
+    use nstack::NStack;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn nth_matches_model(
+            (stack, model) in nstack::<u32, Cardinality>(any::<u32>(), 0..300)
+        ) {
+            for (i, t) in model.iter().enumerate() {
+                prop_assert_eq!(*stack.nth(i as u64).expect("Some(_)"), *t);
+            }
+            prop_assert!(stack.nth(model.len() as u64).is_none());
+        }
+
+        #[test]
+        fn max_key_matches_model(
+            (stack, model) in nstack::<u32, MaxKey<u32>>(any::<u32>(), 0..300)
+        ) {
+            let max = stack.max_key::<u32>().map(|branch| *branch);
+            prop_assert_eq!(max, model.iter().max().copied());
+        }
+    }
+
+    #[test]
+    fn generated_stacks_are_valid() {
+        let mut runner = proptest::test_runner::TestRunner::default();
+        runner
+            .run(
+                &nstack::<u8, Cardinality>(any::<u8>(), 0..100),
+                |(stack, _)| {
+                    let stack: NStack<u8, Cardinality> = stack;
+                    prop_assert_eq!(stack.validate(), Ok(()));
+                    Ok(())
+                },
+            )
+            .expect("valid");
+    }
+}